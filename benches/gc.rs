@@ -0,0 +1,112 @@
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
+
+use atomic::gc::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn pin(c: &mut Criterion) {
+    let global: Global<usize> = Global::default();
+    let local = global.register();
+    c.bench_function("gc/pin", |b| b.iter(|| drop(black_box(local.pin()))));
+}
+
+fn repin(c: &mut Criterion) {
+    let global: Global<usize> = Global::default();
+    let local = global.register();
+    let mut group = c.benchmark_group("gc/repin");
+    // the epoch never moves, every repin takes the fast path
+    group.bench_function("same epoch", |b| {
+        let mut guard = Some(local.pin());
+        b.iter(|| guard = Some(local.repin(black_box(guard.take().unwrap()))));
+    });
+    // what repinning costs without it
+    group.bench_function("unpin and pin", |b| {
+        let mut guard = Some(local.pin());
+        b.iter(|| {
+            drop(black_box(guard.take()));
+            guard = Some(local.pin());
+        });
+    });
+    group.finish();
+}
+
+/// `threads` participants retire `iters` objects in total, advancing as they
+/// migrate.
+fn retire(threads: usize, iters: u64) -> Duration {
+    let global: Global<u64, 64> = Global::default();
+    let per_thread = iters / threads as u64 + 1;
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                let local = global.register();
+                for i in 0..per_thread {
+                    let guard = local.pin();
+                    guard.defer(Box::new(i));
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn defer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gc/defer");
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &n| {
+            b.iter_custom(|iters| retire(n, iters))
+        });
+    }
+    group.finish();
+}
+
+/// 32 participants retire `iters` objects into small bags, so migrations
+/// race for the flags, with advances backing off up to `limit` steps.
+fn contend(limit: u32, iters: u64) -> Duration {
+    let mut global: Global<u64, 4> = Global::default();
+    global.set_backoff_limit(limit);
+    let per_thread = iters / 32 + 1;
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..32 {
+            s.spawn(|| {
+                let local = global.register();
+                for i in 0..per_thread {
+                    let guard = local.pin();
+                    guard.defer(Box::new(i));
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn backoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gc/backoff");
+    for limit in [0, 6] {
+        group.bench_with_input(BenchmarkId::from_parameter(limit), &limit, |b, &limit| {
+            b.iter_custom(|iters| contend(limit, iters))
+        });
+    }
+    group.finish();
+}
+
+fn advance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gc/advance");
+    for participants in [1, 16, 128] {
+        let global: Global<usize> = Global::default();
+        let _locals = global.register_many(participants);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(participants),
+            &participants,
+            |b, _| b.iter(|| black_box(global.try_advance())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pin, repin, defer, backoff, advance);
+criterion_main!(benches);
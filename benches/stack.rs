@@ -0,0 +1,90 @@
+use std::{
+    hint::black_box,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use atomic::gc::stack::AtomicStack;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn push_pop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stack/single");
+    let stack = AtomicStack::default();
+    group.bench_function("push_pop", |b| {
+        b.iter(|| {
+            stack.push(black_box(1_usize));
+            // the only popper
+            black_box(unsafe { stack.boxed_pop() })
+        })
+    });
+    let pooled = AtomicStack::with_pool(1);
+    group.bench_function("push_pop_pooled", |b| {
+        b.iter(|| {
+            pooled.push(black_box(1_usize));
+            black_box(unsafe { pooled.boxed_pop() })
+        })
+    });
+    group.finish();
+}
+
+/// Push a burst of elements, then pop them all.
+fn burst(stack: &AtomicStack<usize>) {
+    for i in 0..100 {
+        stack.push(black_box(i));
+    }
+    // the only popper
+    while unsafe { stack.pop() }.is_some() {}
+}
+
+fn churn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stack/churn");
+    let stack = AtomicStack::default();
+    group.bench_function("default", |b| b.iter(|| burst(&stack)));
+    let pooled = AtomicStack::with_pool(128);
+    group.bench_function("pooled", |b| b.iter(|| burst(&pooled)));
+    group.finish();
+}
+
+/// `pushers` threads push `iters` elements in total while one thread pops.
+fn contended(pushers: usize, iters: u64) -> Duration {
+    let stack = AtomicStack::default();
+    let done = AtomicBool::new(false);
+    let per_thread = iters / pushers as u64 + 1;
+    let start = Instant::now();
+    thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(Ordering::Acquire) {
+                // the only popper
+                black_box(unsafe { stack.boxed_pop() });
+            }
+        });
+        let handles: Vec<_> = (0..pushers)
+            .map(|_| {
+                s.spawn(|| {
+                    for i in 0..per_thread {
+                        stack.push(i);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        done.store(true, Ordering::Release);
+    });
+    start.elapsed()
+}
+
+fn push_pop_contended(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stack/contended");
+    for pushers in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(pushers), &pushers, |b, &n| {
+            b.iter_custom(|iters| contended(n, iters))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, push_pop, churn, push_pop_contended);
+criterion_main!(benches);
@@ -0,0 +1,133 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::gc::stack::AtomicStack;
+
+/// Stack shared by the two ends.
+struct Shared<T>(AtomicStack<T>);
+
+// elements are only moved through the stack, never shared
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// Unbounded multi-producer single-consumer channel on top of an
+/// [`AtomicStack`].
+///
+/// Senders push onto the stack, the receiver detaches everything sent so far
+/// in one step and reverses it to hand items out in FIFO order.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared(AtomicStack::default()));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            buffer: Vec::new(),
+        },
+    )
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) {
+        self.shared.0.push(value);
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Drained items, oldest last.
+    buffer: Vec<T>,
+}
+
+impl<T> Receiver<T> {
+    /// The oldest item not received yet, if any.
+    pub fn try_recv(&mut self) -> Option<T> {
+        if self.buffer.is_empty() {
+            self.refill();
+        }
+        self.buffer.pop()
+    }
+    /// Every item sent so far, oldest first.
+    pub fn recv_batch(&mut self) -> Vec<T> {
+        self.refill();
+        let mut batch = core::mem::take(&mut self.buffer);
+        batch.reverse();
+        batch
+    }
+    /// Whether every [`Sender`] is dropped.
+    pub fn is_disconnected(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+    fn refill(&mut self) {
+        // `&mut self` makes the receiver the only popper
+        // the detached length is exact
+        let batch = unsafe { self.shared.0.pop_all() };
+        if batch.is_empty() {
+            return;
+        }
+        // the batch comes newest first and goes below the older leftovers
+        let older = core::mem::replace(&mut self.buffer, batch.into_iter().collect());
+        self.buffer.extend(older);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::thread;
+
+    use super::channel;
+
+    #[test]
+    fn channel_fifo() {
+        let (sender, mut receiver) = channel();
+        for i in 0..3 {
+            sender.send(i);
+        }
+        assert_eq!(receiver.try_recv(), Some(0));
+        sender.send(3);
+        assert_eq!(receiver.recv_batch(), [1, 2, 3]);
+        assert_eq!(receiver.try_recv(), None);
+        assert!(!receiver.is_disconnected());
+        drop(sender);
+        assert!(receiver.is_disconnected());
+    }
+    #[test]
+    fn channel_producers() {
+        let (sender, mut receiver) = channel();
+        let mut received = vec![Vec::new(); 4];
+        thread::scope(|s| {
+            for producer in 0..4 {
+                let sender = sender.clone();
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        sender.send((producer, i));
+                    }
+                });
+            }
+            drop(sender);
+            while !receiver.is_disconnected() {
+                while let Some((producer, i)) = receiver.try_recv() {
+                    received[producer].push(i);
+                }
+            }
+        });
+        for (producer, i) in receiver.recv_batch() {
+            received[producer].push(i);
+        }
+        // each producer's items arrive once and in order
+        for items in received {
+            assert_eq!(items, (0..1000).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::{any::Any, cell::RefCell, collections::HashMap, mem, ops::Deref, sync::Arc};
+
+use super::gc::{DropReclaim, Global, Local, PinGuard, Reclaim};
+
+thread_local! {
+    /// Per-thread [`Local`]s, keyed by the address of their [`Global`].
+    static HANDLES: RefCell<HashMap<usize, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Thread-local registration, dropped on thread exit.
+struct Handle<T: 'static, const CAP: usize, const N: usize, R: Reclaim<T> + 'static> {
+    // declared first so it drops before the `Global` it borrows
+    local: Local<'static, T, CAP, N, R>,
+    _global: Arc<Global<T, CAP, N, R>>,
+}
+
+impl<T: 'static, const CAP: usize, const N: usize, R: Reclaim<T> + 'static> Handle<T, CAP, N, R> {
+    fn new(global: Arc<Global<T, CAP, N, R>>) -> Self {
+        let local = global.register();
+        // the `Arc` stored alongside keeps the `Global` alive
+        let local = unsafe {
+            mem::transmute::<Local<'_, T, CAP, N, R>, Local<'static, T, CAP, N, R>>(local)
+        };
+        Self {
+            local,
+            _global: global,
+        }
+    }
+}
+
+/// Shared handle to a [`Global`] that registers a [`Local`] for each thread
+/// on first use.
+///
+/// The thread's registration is dropped when the thread exits, flushing its
+/// pending garbage and retiring its flag. Until then it keeps the `Global`
+/// alive.
+#[derive(Debug)]
+pub struct Collector<T, const CAP: usize = 128, const N: usize = 3, R: Reclaim<T> = DropReclaim> {
+    global: Arc<Global<T, CAP, N, R>>,
+}
+
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T>> Clone for Collector<T, CAP, N, R> {
+    fn clone(&self) -> Self {
+        Self {
+            global: self.global.clone(),
+        }
+    }
+}
+
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T> + Default> Default
+    for Collector<T, CAP, N, R>
+{
+    fn default() -> Self {
+        Self {
+            global: Default::default(),
+        }
+    }
+}
+
+impl<T: 'static, const CAP: usize, const N: usize, R: Reclaim<T> + 'static>
+    Collector<T, CAP, N, R>
+{
+    pub fn with_reclaim(reclaim: R) -> Self {
+        Self {
+            global: Arc::new(Global::with_reclaim(reclaim)),
+        }
+    }
+    pub fn global(&self) -> &Global<T, CAP, N, R> {
+        &self.global
+    }
+    fn with_local<U>(&self, f: impl FnOnce(&Local<'static, T, CAP, N, R>) -> U) -> U {
+        let key = Arc::as_ptr(&self.global) as usize;
+        HANDLES.with(|handles| {
+            let handle: *const Handle<T, CAP, N, R> = handles
+                .borrow_mut()
+                .entry(key)
+                .or_insert_with(|| Box::new(Handle::new(self.global.clone())))
+                .downcast_ref()
+                .expect("handle registered for another type");
+            // boxed handles never move and are only dropped on thread exit, so
+            // the map needs not stay borrowed while `f` runs
+            f(&unsafe { &*handle }.local)
+        })
+    }
+    /// Register the current thread's [`Local`] now rather than on first use.
+    ///
+    /// It is retired when the thread exits, even if a guard was leaked, so a
+    /// dead thread never holds back the epoch.
+    pub fn register_current_thread(&self) {
+        self.with_local(|_| ());
+    }
+    /// Pin the current thread's [`Local`], registering it first if needed.
+    pub fn pin(&self) -> PinGuard<'_, T, CAP, N, R> {
+        // the thread's `Local` is only dropped on thread exit, and the guard
+        // is not `Send`
+        self.with_local(|local| unsafe {
+            mem::transmute::<PinGuard<'_, T, CAP, N, R>, PinGuard<'_, T, CAP, N, R>>(local.pin())
+        })
+    }
+    /// Pin a participant of its own, that is not tied to the current thread
+    /// or to `self`'s lifetime.
+    pub fn pin_owned(&self) -> OwnedPinGuard<T, CAP, N, R> {
+        let local = Box::new(Handle::new(self.global.clone()));
+        // the `Local` is boxed and outlives the guard
+        let guard = unsafe {
+            mem::transmute::<PinGuard<'_, T, CAP, N, R>, PinGuard<'static, T, CAP, N, R>>(
+                local.local.pin(),
+            )
+        };
+        OwnedPinGuard { guard, local }
+    }
+    /// See [`Local::pin_and_defer`].
+    pub fn migrate(&self, garbage: Box<T>) {
+        self.with_local(|local| local.pin_and_defer(garbage))
+    }
+    /// See [`Local::defer`].
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, guard: &PinGuard<T, CAP, N, R>, f: F) {
+        self.with_local(|local| local.defer(guard, f))
+    }
+    /// See [`Local::flush`].
+    pub fn flush(&self, guard: &PinGuard<T, CAP, N, R>) {
+        self.with_local(|local| local.flush(guard))
+    }
+}
+
+/// Pinned participant keeping its [`Collector`] alive, see
+/// [`Collector::pin_owned`].
+///
+/// Dropping it unpins and retires the participant, flushing its garbage.
+pub struct OwnedPinGuard<
+    T: 'static,
+    const CAP: usize = 128,
+    const N: usize = 3,
+    R: Reclaim<T> + 'static = DropReclaim,
+> {
+    // declared first so it drops before the `Local` it borrows
+    guard: PinGuard<'static, T, CAP, N, R>,
+    local: Box<Handle<T, CAP, N, R>>,
+}
+
+// the participant is owned by the guard alone, nothing else touches its
+// thread-local state
+unsafe impl<T: Send, const CAP: usize, const N: usize, R: Reclaim<T> + Send + Sync> Send
+    for OwnedPinGuard<T, CAP, N, R>
+{
+}
+
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T>> Deref for OwnedPinGuard<T, CAP, N, R> {
+    type Target = PinGuard<'static, T, CAP, N, R>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T>> OwnedPinGuard<T, CAP, N, R> {
+    /// See [`Local::pin_and_defer`].
+    pub fn migrate(&self, garbage: Box<T>) {
+        self.guard.defer(garbage)
+    }
+    /// See [`Local::defer`].
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.local.local.defer(&self.guard, f)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::{
+        mem,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+    };
+
+    use super::Collector;
+
+    #[test]
+    fn collector_threads() {
+        let collector: Collector<usize> = Collector::default();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let collector = collector.clone();
+                let ran = ran.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let guard = collector.pin();
+                        let ran = ran.clone();
+                        collector.defer(&guard, move || {
+                            ran.fetch_add(1, Ordering::Relaxed);
+                        });
+                        guard.defer(Box::new(i));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // exited threads flushed their bags
+        for _ in 0..3 {
+            collector.global().try_advance();
+        }
+        assert_eq!(ran.load(Ordering::Relaxed), 400);
+    }
+    #[test]
+    fn collector_thread_exit() {
+        let collector: Collector<usize> = Collector::default();
+        let (pinned, is_pinned) = mpsc::channel();
+        let (exit, should_exit) = mpsc::channel::<()>();
+        let thread = thread::spawn({
+            let collector = collector.clone();
+            move || {
+                collector.register_current_thread();
+                // exits without ever unpinning
+                mem::forget(collector.pin());
+                pinned.send(()).unwrap();
+                should_exit.recv().unwrap();
+            }
+        });
+        is_pinned.recv().unwrap();
+        assert!(collector.global().try_advance());
+        assert!(!collector.global().try_advance());
+
+        exit.send(()).unwrap();
+        thread.join().unwrap();
+        for _ in 0..3 {
+            assert!(collector.global().try_advance());
+        }
+        assert_eq!(collector.global().stats().registered, 0);
+    }
+    #[test]
+    fn collector_pin_owned() {
+        let collector: Collector<usize> = Collector::default();
+        let ran = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+        let worker = thread::spawn(move || {
+            for task in receiver {
+                task();
+            }
+        });
+
+        let guard = collector.pin_owned();
+        let flag = ran.clone();
+        guard.defer(move || flag.store(true, Ordering::Relaxed));
+        assert!(collector.global().try_advance());
+        assert!(!collector.global().try_advance());
+
+        sender.send(Box::new(move || drop(guard))).unwrap();
+        drop(sender);
+        worker.join().unwrap();
+
+        for _ in 0..3 {
+            assert!(collector.global().try_advance());
+        }
+        assert!(ran.load(Ordering::Relaxed));
+    }
+}
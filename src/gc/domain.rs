@@ -0,0 +1,186 @@
+use alloc::boxed::Box;
+use core::fmt;
+
+use super::{
+    gc::{Global, Local, PinGuard, Reclaim},
+    hazard::{HazardGlobal, HazardGuard, HazardLocal},
+};
+use crate::sync::atomic::{AtomicPtr, Ordering};
+
+/// Reclamation scheme shared by [`Global`] and [`HazardGlobal`], so code
+/// written against a `Domain` switches from epochs to hazard pointers by
+/// changing the type it is instantiated with.
+pub trait Domain<T> {
+    type Participant<'a>: Participant<T>
+    where
+        Self: 'a;
+
+    fn register(&self) -> Self::Participant<'_>;
+}
+
+/// Participant of a [`Domain`], see [`Local`] and [`HazardLocal`].
+pub trait Participant<T> {
+    type Guard<'p>: Protected<T>
+    where
+        Self: 'p;
+
+    /// Load `src` and keep the loaded pointee from being reclaimed until the
+    /// guard drops: pinned for epochs, published as the hazard otherwise.
+    ///
+    /// # Safety
+    ///
+    /// `src` must only ever hold null or allocations retired through this
+    /// participant's domain.
+    unsafe fn protect(&self, src: &AtomicPtr<T>) -> Self::Guard<'_>;
+    /// Retire `garbage`, already unlinked from shared memory.
+    fn retire(&self, garbage: Box<T>);
+}
+
+/// Pointer kept alive by [`Participant::protect`].
+pub trait Protected<T> {
+    fn as_ptr(&self) -> *mut T;
+    /// The protected value, `None` if the loaded pointer was null.
+    fn as_ref(&self) -> Option<&T> {
+        // protected until the guard drops
+        unsafe { self.as_ptr().as_ref() }
+    }
+}
+
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T>> Domain<T> for Global<T, CAP, N, R> {
+    type Participant<'a>
+        = Local<'a, T, CAP, N, R>
+    where
+        Self: 'a;
+
+    fn register(&self) -> Self::Participant<'_> {
+        Global::register(self)
+    }
+}
+
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> Participant<T>
+    for Local<'a, T, CAP, N, R>
+{
+    type Guard<'p>
+        = PinnedPtr<'p, T, CAP, N, R>
+    where
+        Self: 'p;
+
+    unsafe fn protect(&self, src: &AtomicPtr<T>) -> Self::Guard<'_> {
+        let guard = self.pin();
+        PinnedPtr {
+            raw: src.load(Ordering::Acquire),
+            _guard: guard,
+        }
+    }
+    fn retire(&self, garbage: Box<T>) {
+        self.pin().defer(garbage);
+    }
+}
+
+/// Pointer loaded under a [`PinGuard`], see [`Participant::protect`].
+pub struct PinnedPtr<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> {
+    raw: *mut T,
+    _guard: PinGuard<'a, T, CAP, N, R>,
+}
+
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> Protected<T>
+    for PinnedPtr<'a, T, CAP, N, R>
+{
+    fn as_ptr(&self) -> *mut T {
+        self.raw
+    }
+}
+
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> fmt::Debug
+    for PinnedPtr<'a, T, CAP, N, R>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PinnedPtr").field(&self.raw).finish()
+    }
+}
+
+impl<T, const CAP: usize, R: Reclaim<T>> Domain<T> for HazardGlobal<T, CAP, R> {
+    type Participant<'a>
+        = HazardLocal<'a, T, CAP, R>
+    where
+        Self: 'a;
+
+    fn register(&self) -> Self::Participant<'_> {
+        HazardGlobal::register(self)
+    }
+}
+
+impl<'a, T, const CAP: usize, R: Reclaim<T>> Participant<T> for HazardLocal<'a, T, CAP, R> {
+    type Guard<'p>
+        = HazardGuard<'p, T>
+    where
+        Self: 'p;
+
+    unsafe fn protect(&self, src: &AtomicPtr<T>) -> Self::Guard<'_> {
+        HazardLocal::protect(self, src)
+    }
+    fn retire(&self, garbage: Box<T>) {
+        HazardLocal::retire(self, garbage);
+    }
+}
+
+impl<'a, T> Protected<T> for HazardGuard<'a, T> {
+    fn as_ptr(&self) -> *mut T {
+        HazardGuard::as_ptr(self)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::{
+        ptr,
+        sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::{Domain, Participant, Protected};
+    use crate::gc::{gc::Global, hazard::HazardGlobal};
+
+    struct Counted<'a>(usize, &'a AtomicUsize);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Readers protect the current value while a writer keeps replacing it.
+    fn churn<'a, D: Domain<Counted<'a>> + Sync>(domain: &D, dropped: &'a AtomicUsize) {
+        let src = AtomicPtr::new(Box::into_raw(Box::new(Counted(0, dropped))));
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let participant = domain.register();
+                    for _ in 0..1000 {
+                        let guard = unsafe { participant.protect(&src) };
+                        assert!(guard.as_ref().unwrap().0 < 1000);
+                    }
+                });
+            }
+            let participant = domain.register();
+            for i in 1..1000 {
+                let new = Box::into_raw(Box::new(Counted(i, dropped)));
+                let old = src.swap(new, Ordering::AcqRel);
+                participant.retire(unsafe { Box::from_raw(old) });
+            }
+        });
+        let last = src.swap(ptr::null_mut(), Ordering::AcqRel);
+        domain.register().retire(unsafe { Box::from_raw(last) });
+    }
+
+    #[test]
+    fn domain_switch() {
+        let dropped = AtomicUsize::new(0);
+        churn(&Global::<_, 4>::default(), &dropped);
+        assert_eq!(dropped.load(Ordering::Relaxed), 1000);
+
+        let dropped = AtomicUsize::new(0);
+        churn(&HazardGlobal::<_, 4>::default(), &dropped);
+        assert_eq!(dropped.load(Ordering::Relaxed), 1000);
+    }
+}
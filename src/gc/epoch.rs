@@ -1,148 +1,330 @@
-use std::{
-    mem,
-    sync::atomic::{AtomicUsize, Ordering},
-};
-
-#[derive(Debug, Copy, Clone, PartialEq)]
-#[repr(usize)]
-pub enum Epoch {
-    Epoch0 = 0,
-    Epoch1 = 1,
-    Epoch2 = 2,
-}
+use core::fmt;
 
-impl Default for Epoch {
-    fn default() -> Self {
-        Epoch::Epoch0
-    }
-}
+use crate::sync::atomic::{AtomicUsize, Ordering};
 
-impl Epoch {
+/// Alignment of [`AtomicEpoch`] and [`AtomicFlag`], padding them to their own
+/// cache line to avoid false sharing between adjacent flags.
+#[cfg(not(feature = "cache-line-64"))]
+pub const CACHE_LINE: usize = 128;
+#[cfg(feature = "cache-line-64")]
+pub const CACHE_LINE: usize = 64;
+
+/// Raw value that does not encode an [`Epoch`] or [`Flag`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OutOfRange(pub usize);
+
+/// Global epoch, counting modulo `N`.
+///
+/// `N` is the number of epochs garbage is spread over, a larger `N` widens
+/// the grace window. It must be at least 3.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Epoch<const N: usize = 3>(usize);
+
+impl<const N: usize> Epoch<N> {
+    /// # Panics
+    ///
+    /// If `value` is not below `N`.
+    #[inline]
+    pub const fn new(value: usize) -> Self {
+        const { assert!(N >= 3, "epoch count must be at least 3") };
+        assert!(value < N, "epoch out of range");
+        Self(value)
+    }
+    #[inline]
+    pub const fn value(self) -> usize {
+        self.0
+    }
     #[inline]
     pub fn increase(self) -> Self {
-        unsafe { mem::transmute((self as usize + 1) % 3) }
+        Self((self.0 + 1) % N)
     }
     #[inline]
     pub fn decrease(self) -> Self {
-        unsafe { mem::transmute((self as usize + 2) % 3) }
+        Self((self.0 + N - 1) % N)
+    }
+    /// How many times `self` has to be increased to reach `other`.
+    #[inline]
+    pub fn distance(self, other: Self) -> usize {
+        (other.0 + N - self.0) % N
     }
 }
 
-#[cfg(target_pointer_width = "64")]
-#[repr(C, align(128))]
-#[derive(Debug)]
-pub struct AtomicEpoch(AtomicUsize);
+impl<const N: usize> fmt::Display for Epoch<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "epoch({})", self.0)
+    }
+}
+
+impl<const N: usize> TryFrom<usize> for Epoch<N> {
+    type Error = OutOfRange;
 
-#[cfg(not(target_pointer_width = "64"))]
-#[derive(Debug, Default)]
-pub struct AtomicEpoch(AtomicUsize);
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        if value < N {
+            Ok(Self(value))
+        } else {
+            Err(OutOfRange(value))
+        }
+    }
+}
 
-impl AtomicEpoch {
+#[cfg_attr(not(feature = "cache-line-64"), repr(C, align(128)))]
+#[cfg_attr(feature = "cache-line-64", repr(C, align(64)))]
+#[derive(Debug)]
+pub struct AtomicEpoch<const N: usize = 3>(AtomicUsize);
+
+impl<const N: usize> AtomicEpoch<N> {
+    #[inline]
+    pub fn store(&self, epoch: Epoch<N>, ordering: Ordering) {
+        self.0.store(epoch.0, ordering);
+    }
     #[inline]
-    pub fn store(&self, epoch: Epoch, ordering: Ordering) {
-        self.0.store(epoch as usize, ordering);
+    pub fn load(&self, ordering: Ordering) -> Epoch<N> {
+        // only ever stores valid epochs
+        let value = self.0.load(ordering);
+        debug_assert_eq!(Epoch::<N>::try_from(value), Ok(Epoch(value)));
+        Epoch(value)
     }
+    /// Single attempt to replace `old` with `new`, returning the observed epoch.
     #[inline]
-    pub fn load(&self, ordering: Ordering) -> Epoch {
-        unsafe { mem::transmute(self.0.load(ordering)) }
+    pub fn compare_exchange(&self, old: Epoch<N>, new: Epoch<N>) -> Result<Epoch<N>, Epoch<N>> {
+        self.0
+            .compare_exchange(old.0, new.0, Ordering::SeqCst, Ordering::Acquire)
+            .map(Epoch)
+            .map_err(Epoch)
     }
+    /// Spin until `old` is replaced by `new`, never returns if `old` is never
+    /// observed again.
     #[inline]
-    pub fn compare_and_swap(&self, old: Epoch, new: Epoch) {
+    pub fn compare_and_swap_blocking(&self, old: Epoch<N>, new: Epoch<N>) {
         while self
             .0
-            .compare_exchange(
-                old as usize,
-                new as usize,
-                Ordering::SeqCst,
-                Ordering::Acquire,
-            )
+            .compare_exchange(old.0, new.0, Ordering::SeqCst, Ordering::Acquire)
             .is_err()
         {}
     }
 }
 
-impl Default for AtomicEpoch {
+impl<const N: usize> Default for AtomicEpoch<N> {
     fn default() -> Self {
-        Self(AtomicUsize::new(Epoch::default() as usize))
+        Self(AtomicUsize::new(Epoch::<N>::default().0))
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-#[repr(usize)]
-pub enum Flag {
-    Epoch0 = 0,
-    Epoch1 = 1,
-    Epoch2 = 2,
-    Unpin = 3,
-}
+/// State of a participant: pinned at some [`Epoch`], [`Flag::UNPIN`] or
+/// [`Flag::RETIRED`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Flag<const N: usize = 3>(usize);
+
+impl<const N: usize> Flag<N> {
+    pub const UNPIN: Self = Self(N);
+    /// Owning [`Local`](super::gc::Local) was dropped, the flag can be reused.
+    pub const RETIRED: Self = Self(N + 1);
 
-impl Flag {
     #[inline]
     pub fn value(self) -> usize {
-        debug_assert_ne!(self, Flag::Unpin);
-        self as usize
+        debug_assert!(self != Self::UNPIN && self != Self::RETIRED);
+        self.0
     }
     #[inline]
-    pub fn from_epoch(epoch: Epoch) -> Self {
-        unsafe { mem::transmute(epoch) }
+    pub fn from_epoch(epoch: Epoch<N>) -> Self {
+        Self(epoch.0)
+    }
+    /// Epoch the flag is pinned at, `None` if unpinned or retired.
+    #[inline]
+    pub fn as_epoch(self) -> Option<Epoch<N>> {
+        Epoch::try_from(self.0).ok()
     }
 }
 
-impl Default for Flag {
+impl<const N: usize> Default for Flag<N> {
     fn default() -> Self {
-        Flag::Unpin
+        Self::UNPIN
     }
 }
 
-#[cfg(target_pointer_width = "64")]
-#[repr(C, align(128))]
-#[derive(Debug)]
-pub struct AtomicFlag(AtomicUsize);
+impl<const N: usize> fmt::Display for Flag<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.as_epoch() {
+            Some(epoch) => write!(f, "pinned({})", epoch.value()),
+            None if *self == Self::UNPIN => f.write_str("unpin"),
+            None => f.write_str("retired"),
+        }
+    }
+}
+
+impl<const N: usize> TryFrom<usize> for Flag<N> {
+    type Error = OutOfRange;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        if value <= Self::RETIRED.0 {
+            Ok(Self(value))
+        } else {
+            Err(OutOfRange(value))
+        }
+    }
+}
 
-#[cfg(not(target_pointer_width = "64"))]
-#[derive(Debug, Default)]
-pub struct AtomicFlag(AtomicUsize);
+#[cfg_attr(not(feature = "cache-line-64"), repr(C, align(128)))]
+#[cfg_attr(feature = "cache-line-64", repr(C, align(64)))]
+#[derive(Debug)]
+pub struct AtomicFlag<const N: usize = 3>(AtomicUsize);
 
-impl AtomicFlag {
+impl<const N: usize> AtomicFlag<N> {
+    #[inline]
+    pub fn store(&self, flag: Flag<N>, ordering: Ordering) {
+        self.0.store(flag.0, ordering);
+    }
     #[inline]
-    pub fn store(&self, flag: Flag, ordering: Ordering) {
-        self.0.store(flag as usize, ordering);
+    pub fn load(&self, ordering: Ordering) -> Flag<N> {
+        // only ever stores valid flags
+        let value = self.0.load(ordering);
+        debug_assert_eq!(Flag::<N>::try_from(value), Ok(Flag(value)));
+        Flag(value)
     }
+    /// Single attempt to replace `old` with `new`, returning the observed flag.
     #[inline]
-    pub fn load(&self, ordering: Ordering) -> Flag {
-        unsafe { mem::transmute(self.0.load(ordering)) }
+    pub fn compare_exchange(&self, old: Flag<N>, new: Flag<N>) -> Result<Flag<N>, Flag<N>> {
+        self.0
+            .compare_exchange(old.0, new.0, Ordering::SeqCst, Ordering::Acquire)
+            .map(Flag)
+            .map_err(Flag)
     }
+    /// Store `flag` and order it before every later load of this thread.
     #[inline]
-    pub fn compare_and_swap(&self, old: Flag, new: Flag) {
+    pub fn publish(&self, flag: Flag<N>) {
+        self.0.store(flag.0, Ordering::SeqCst);
+        crate::sync::atomic::fence(Ordering::SeqCst);
+    }
+    /// Whether the participant holds back advancing the global epoch from
+    /// `epoch`, i.e. it is pinned at the epoch before, whose garbage it may
+    /// still reach.
+    ///
+    /// Unpinned and retired participants never block, nor do those pinned at
+    /// `epoch` already.
+    #[inline]
+    pub fn blocks_epoch(&self, epoch: Epoch<N>) -> bool {
+        match self.load(Ordering::Acquire).as_epoch() {
+            Some(pinned) => pinned == epoch.decrease(),
+            None => false,
+        }
+    }
+    /// Spin until `old` is replaced by `new`, never returns if `old` is never
+    /// observed again.
+    #[inline]
+    pub fn compare_and_swap_blocking(&self, old: Flag<N>, new: Flag<N>) {
         while self
             .0
-            .compare_exchange(
-                old as usize,
-                new as usize,
-                Ordering::SeqCst,
-                Ordering::Acquire,
-            )
+            .compare_exchange(old.0, new.0, Ordering::SeqCst, Ordering::Acquire)
             .is_err()
         {}
     }
 }
 
-impl Default for AtomicFlag {
+impl<const N: usize> Default for AtomicFlag<N> {
     fn default() -> Self {
-        Self(AtomicUsize::new(Flag::default() as usize))
+        Self(AtomicUsize::new(Flag::<N>::default().0))
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(loom)))]
 pub mod test {
-    use std::mem;
+    use std::{mem, sync::atomic::Ordering};
 
-    use super::Flag;
+    use super::{AtomicEpoch, AtomicFlag, Epoch, Flag, OutOfRange, CACHE_LINE};
 
     #[test]
     fn transmute_enum() {
         let a = 3_usize;
         let flag: Flag = unsafe { mem::transmute(a) };
-        assert_eq!(flag, Flag::Unpin);
+        assert_eq!(flag, Flag::UNPIN);
+    }
+    #[test]
+    fn cache_line_alignment() {
+        const _: () = assert!(mem::align_of::<AtomicEpoch>() == CACHE_LINE);
+        const _: () = assert!(mem::align_of::<AtomicFlag>() == CACHE_LINE);
+    }
+    #[test]
+    fn try_from_usize() {
+        for i in 0..3 {
+            assert_eq!(Epoch::<3>::try_from(i), Ok(Epoch::new(i)));
+            assert_eq!(Flag::<3>::try_from(i), Ok(Flag::from_epoch(Epoch::new(i))));
+        }
+        assert_eq!(Flag::<3>::try_from(3), Ok(Flag::UNPIN));
+        assert_eq!(Flag::<3>::try_from(4), Ok(Flag::RETIRED));
+        assert_eq!(Epoch::<3>::try_from(3), Err(OutOfRange(3)));
+        assert_eq!(Epoch::<3>::try_from(7), Err(OutOfRange(7)));
+        assert_eq!(Flag::<3>::try_from(7), Err(OutOfRange(7)));
+    }
+    #[test]
+    fn compare_exchange_once() {
+        let epoch: AtomicEpoch = AtomicEpoch::default();
+        let (e0, e1, e2) = (Epoch::new(0), Epoch::new(1), Epoch::new(2));
+        assert_eq!(epoch.compare_exchange(e0, e1), Ok(e0));
+        assert_eq!(epoch.compare_exchange(e0, e2), Err(e1));
+        epoch.compare_and_swap_blocking(e1, e2);
+        assert_eq!(epoch.load(Ordering::Relaxed), e2);
+
+        let flag: AtomicFlag = AtomicFlag::default();
+        let pinned = Flag::from_epoch(e0);
+        assert_eq!(flag.compare_exchange(Flag::UNPIN, pinned), Ok(Flag::UNPIN));
+        assert_eq!(flag.compare_exchange(Flag::UNPIN, pinned), Err(pinned));
+        flag.compare_and_swap_blocking(pinned, Flag::UNPIN);
+        assert_eq!(flag.load(Ordering::Relaxed), Flag::UNPIN);
+    }
+    #[test]
+    fn flag_epoch_round_trip() {
+        for i in 0..3 {
+            let epoch: Epoch = Epoch::new(i);
+            assert_eq!(Flag::from_epoch(epoch).as_epoch(), Some(epoch));
+        }
+        assert_eq!(Flag::<3>::UNPIN.as_epoch(), None);
+        assert_eq!(Flag::<3>::RETIRED.as_epoch(), None);
+    }
+    #[test]
+    fn display() {
+        let epoch: Epoch = Epoch::new(0);
+        assert_eq!(epoch.to_string(), "epoch(0)");
+        assert_eq!(Flag::from_epoch(epoch.increase()).to_string(), "pinned(1)");
+        assert_eq!(Flag::<3>::UNPIN.to_string(), "unpin");
+        assert_eq!(Flag::<3>::RETIRED.to_string(), "retired");
+    }
+    #[test]
+    fn flag_blocks_epoch() {
+        let flag: AtomicFlag = AtomicFlag::default();
+        for epoch in (0..3).map(Epoch::new) {
+            for pinned in (0..3).map(Epoch::new) {
+                flag.store(Flag::from_epoch(pinned), Ordering::Relaxed);
+                assert_eq!(flag.blocks_epoch(epoch), pinned.increase() == epoch);
+            }
+            for state in [Flag::UNPIN, Flag::RETIRED] {
+                flag.store(state, Ordering::Relaxed);
+                assert!(!flag.blocks_epoch(epoch));
+            }
+        }
+    }
+    #[test]
+    fn epoch_distance() {
+        let expected = [[0, 1, 2], [2, 0, 1], [1, 2, 0]];
+        for (from, row) in expected.iter().enumerate() {
+            for (to, &distance) in row.iter().enumerate() {
+                let (from, to): (Epoch, Epoch) = (Epoch::new(from), Epoch::new(to));
+                assert_eq!(from.distance(to), distance);
+            }
+        }
+        assert_eq!(Epoch::<4>::new(3).distance(Epoch::new(1)), 2);
+    }
+    #[test]
+    fn epoch_wraparound() {
+        let epochs: Vec<Epoch<4>> = (0..4).map(Epoch::new).collect();
+        for i in 0..4 {
+            assert_eq!(epochs[i].increase(), epochs[(i + 1) % 4]);
+            assert_eq!(epochs[i].decrease(), epochs[(i + 3) % 4]);
+        }
+        assert_eq!(Epoch::<4>::new(3).increase().value(), 0);
+        assert_eq!(Epoch::<4>::new(0).decrease().value(), 3);
+        assert_eq!(Epoch::<3>::new(2).increase(), Epoch::new(0));
+        assert_eq!(Flag::<4>::UNPIN, Flag(4));
     }
 }
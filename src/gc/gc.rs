@@ -1,131 +1,1115 @@
-use std::{
-    cell::Cell,
-    mem,
-    sync::atomic::{fence, Ordering},
+use alloc::{boxed::Box, vec::Vec};
+use core::{array, cell::Cell, fmt, marker::PhantomData, mem, ptr};
+
+use crate::sync::{
+    self,
+    atomic::{fence, AtomicUsize, Ordering},
 };
 
 use super::{
     epoch::{AtomicEpoch, AtomicFlag, Epoch, Flag},
-    stack::AtomicStack,
+    stack::{AtomicStack, StackGuard},
 };
 
-#[derive(Debug)]
-struct Bag<T, const CAP: usize> {
-    data: Vec<Box<T>>,
+type Deferred = Box<dyn FnOnce() + Send>;
+
+/// Garbage of one participant, stored inline so filling it never allocates.
+///
+/// Only handed out by [`Global::steal_bag`], dropping it drops the remaining
+/// garbage and runs the deferred closures.
+pub struct Bag<T, const CAP: usize> {
+    /// The first `len` slots are taken.
+    data: [Option<Box<T>>; CAP],
+    len: usize,
+    deferred: Vec<Deferred>,
+}
+
+impl<T: fmt::Debug, const CAP: usize> fmt::Debug for Bag<T, CAP> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bag")
+            .field("data", &&self.data[..self.len])
+            .field("deferred", &self.deferred.len())
+            .finish()
+    }
 }
 
 impl<T, const CAP: usize> Default for Bag<T, CAP> {
     fn default() -> Self {
         Self {
-            data: Vec::with_capacity(CAP),
+            data: array::from_fn(|_| None),
+            len: 0,
+            deferred: Vec::new(),
         }
     }
 }
 
+/// Runs the deferred closures once the bag is reclaimed.
+impl<T, const CAP: usize> Drop for Bag<T, CAP> {
+    fn drop(&mut self) {
+        self.run_deferred();
+    }
+}
+
 impl<T, const CAP: usize> Bag<T, CAP> {
+    /// Objects and deferred closures in the bag.
+    pub fn len(&self) -> usize {
+        self.len + self.deferred.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     fn is_full(&self) -> bool {
-        self.data.len() == CAP
+        self.len() >= CAP
     }
+    /// The bag must not be full, see [`Local::fill_bag`].
     fn push(&mut self, value: Box<T>) {
-        self.data.push(value);
+        self.data[self.len] = Some(value);
+        self.len += 1;
+    }
+    /// Take the objects out, leaving the deferred closures.
+    pub fn drain(&mut self) -> impl Iterator<Item = Box<T>> + '_ {
+        let len = mem::take(&mut self.len);
+        self.data[..len].iter_mut().filter_map(Option::take)
+    }
+    fn defer(&mut self, f: Deferred) {
+        self.deferred.push(f);
+    }
+    fn run_deferred(&mut self) {
+        for f in self.deferred.drain(..) {
+            f();
+        }
     }
 }
 
+/// Default of [`Global::set_backoff_limit`].
+const DEFAULT_BACKOFF_LIMIT: u32 = 6;
+/// Stalled migrations after which the next one waits for the flags.
+const STARVATION_LIMIT: usize = 64;
+/// Attempts of a starved migration to take the flags.
+const STARVED_ATTEMPTS: usize = 128;
+
+/// Why the epoch didn't advance.
+enum Stall {
+    /// Another thread owns the flags.
+    Contended,
+    /// Some participant is pinned in the previous epoch.
+    Blocked,
+}
+
+/// Per-participant state of the advance backoff, see
+/// [`Global::set_backoff_limit`].
 #[derive(Debug, Default)]
-pub struct Global<T, const CAP: usize=128> {
-    epoch: AtomicEpoch,
-    bags: [AtomicStack<Bag<T, CAP>>; 3],
-    flags: AtomicStack<AtomicFlag>,
+struct Backoff {
+    step: Cell<u32>,
+    /// Advance attempts left to skip.
+    skip: Cell<usize>,
 }
 
-impl<T, const CAP: usize> Global<T, CAP> {
-    pub fn register<'a>(&'a self) -> Local<'a, T, CAP> {
-        let flag = self.flags.push(Default::default());
-        debug_assert_eq!(flag.load(Ordering::Relaxed), Flag::default());
-        let local = Local {
-            bag: Default::default(),
-            flag: &flag,
-            global: &self,
+/// Snapshot of a [`Global`]'s counters, see [`Global::stats`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Objects and deferred closures handed to the global bags.
+    pub migrated: usize,
+    pub advances: usize,
+    pub reclaimed_bags: usize,
+    /// Objects and deferred closures in the global bags, not reclaimed yet.
+    pub pending: usize,
+    /// Live [`Local`]s.
+    pub registered: usize,
+    pub pinned: usize,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    migrated: AtomicUsize,
+    advances: AtomicUsize,
+    reclaimed_bags: AtomicUsize,
+    pending: AtomicUsize,
+    registered: AtomicUsize,
+}
+
+type Hook = Box<dyn Fn(&Stats) + Send + Sync>;
+
+/// See [`Global::on_migrate`].
+struct HighWater {
+    mark: usize,
+    hook: Hook,
+}
+
+impl fmt::Debug for HighWater {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HighWater")
+            .field("mark", &self.mark)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Frees garbage once no participant can observe it anymore.
+pub trait Reclaim<T> {
+    fn reclaim(&self, garbage: T);
+}
+
+/// Just drops the garbage.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DropReclaim;
+
+impl<T> Reclaim<T> for DropReclaim {
+    #[inline]
+    fn reclaim(&self, garbage: T) {
+        drop(garbage);
+    }
+}
+
+/// Epoch based garbage collector, spreading garbage over `N` epochs.
+///
+/// Reclaiming needs at least 3 epochs, fewer don't compile:
+///
+/// ```compile_fail
+/// use atomic::gc::prelude::*;
+///
+/// let global: Global<usize, 128, 2> = Global::default();
+/// ```
+pub struct Global<T, const CAP: usize = 128, const N: usize = 3, R: Reclaim<T> = DropReclaim> {
+    epoch: AtomicEpoch<N>,
+    bags: [AtomicStack<Bag<T, CAP>>; N],
+    flags: AtomicStack<AtomicFlag<N>>,
+    counters: Counters,
+    /// Cap on the exponent of [`Backoff`], `0` disables it.
+    backoff_limit: u32,
+    /// Migrations that couldn't attempt an advance since the last one.
+    stalled: AtomicUsize,
+    high_water: Option<HighWater>,
+    reclaim: R,
+}
+
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T> + Default> Default
+    for Global<T, CAP, N, R>
+{
+    fn default() -> Self {
+        Self::with_reclaim(Default::default())
+    }
+}
+
+/// Prints the counters only: the bags are popped by whoever owns the flags,
+/// without holding their own guard, so walking them could read freed nodes.
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T> + fmt::Debug> fmt::Debug
+    for Global<T, CAP, N, R>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Global")
+            .field("epoch", &self.epoch.load(Ordering::Relaxed))
+            .field("counters", &self.counters)
+            .field("backoff_limit", &self.backoff_limit)
+            .field("high_water", &self.high_water)
+            .field("reclaim", &self.reclaim)
+            .finish_non_exhaustive()
+    }
+}
+
+// garbage is moved to and reclaimed by whichever thread advances the epoch,
+// and deferred closures are already `Send`
+unsafe impl<T: Send, const CAP: usize, const N: usize, R: Reclaim<T> + Send> Send
+    for Global<T, CAP, N, R>
+{
+}
+// participants on every thread push garbage and advance through `&self`
+unsafe impl<T: Send, const CAP: usize, const N: usize, R: Reclaim<T> + Sync> Sync
+    for Global<T, CAP, N, R>
+{
+}
+
+/// Reclaims the garbage that is still pending.
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T>> Drop for Global<T, CAP, N, R> {
+    fn drop(&mut self) {
+        for bags in &self.bags {
+            while let Some(mut bag) = unsafe { bags.boxed_pop() } {
+                self.reclaim_bag(&mut bag);
+            }
+        }
+    }
+}
+
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T>> Global<T, CAP, N, R> {
+    pub fn with_reclaim(reclaim: R) -> Self {
+        const { assert!(N >= 3, "epoch count must be at least 3") };
+        Self {
+            epoch: Default::default(),
+            bags: array::from_fn(|_| Default::default()),
+            flags: Default::default(),
+            counters: Default::default(),
+            backoff_limit: DEFAULT_BACKOFF_LIMIT,
+            stalled: AtomicUsize::new(0),
+            high_water: None,
+            reclaim,
+        }
+    }
+    /// A participant losing the race to advance the epoch after migrating
+    /// skips its next `2^k - 1` attempts, `k` growing on each loss up to
+    /// `limit` and resetting on success. This cuts contention on the flags
+    /// when many threads migrate at once, `0` disables it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` doesn't fit the shift of a `usize`.
+    pub fn set_backoff_limit(&mut self, limit: u32) {
+        assert!(limit < usize::BITS, "backoff limit too large");
+        self.backoff_limit = limit;
+    }
+    /// Call `hook` from the migrating thread whenever the pending garbage
+    /// climbs from below `high_water` to at least it, e.g. to flush or
+    /// advance more eagerly. Replaces the previous hook.
+    ///
+    /// The hook may fire while the flags are owned, e.g. from a deferred
+    /// closure or a [`Reclaim`] migrating during an advance. [`Stats::pinned`]
+    /// is then left at `0`, and calling methods that wait for the flags,
+    /// such as [`Global::stats`], would deadlock. Anything else may be called
+    /// back.
+    pub fn on_migrate(&mut self, high_water: usize, hook: impl Fn(&Stats) + Send + Sync + 'static) {
+        self.high_water = Some(HighWater {
+            mark: high_water,
+            hook: Box::new(hook),
+        });
+    }
+    /// Keep `bags_per_epoch` spare nodes in each epoch's bag stack, so the
+    /// first migrations don't allocate on the hot path.
+    ///
+    /// Trades memory for latency: reclaimed nodes are recycled rather than
+    /// freed until the `Global` drops.
+    pub fn preallocate(&mut self, bags_per_epoch: usize) {
+        for bags in &mut self.bags {
+            let pooled = AtomicStack::with_pool(bags_per_epoch);
+            // exclusive access, nobody else pops
+            drop(unsafe { pooled.swap(mem::take(bags)) });
+            pooled.reserve(bags_per_epoch);
+            *bags = pooled;
+        }
+    }
+    /// Register a new participant, reusing the flag of a dropped [`Local`] if
+    /// one is available.
+    ///
+    /// Registering is safe at any time, even while other threads migrate and
+    /// advance. The flag starts unpinned, which blocks nothing since an
+    /// unpinned participant references nothing. Its first [`Local::pin`]
+    /// publishes the flag and checks the global epoch again afterwards, so
+    /// the guard's epoch is the live one as seen by any scan that misses the
+    /// flag, and garbage it loads afterwards can't be reclaimed before it
+    /// unpins.
+    pub fn register(&self) -> Local<'_, T, CAP, N, R> {
+        let flag = self.reuse_flag().unwrap_or_else(|| {
+            let flag = self.flags.push(Default::default());
+            debug_assert_eq!(flag.load(Ordering::Relaxed), Flag::default());
+            flag
+        });
+        self.counters.registered.fetch_add(1, Ordering::Relaxed);
+        self.local(flag)
+    }
+    /// Register `n` participants, reusing retired flags first and linking the
+    /// missing ones in a single push.
+    pub fn register_many(&self, n: usize) -> Vec<Local<'_, T, CAP, N, R>> {
+        let mut flags: Vec<&AtomicFlag<N>> = match self.flags.try_own() {
+            Some(stack_guard) => self
+                .flags
+                .iter(&stack_guard)
+                .filter(|flag| flag.compare_exchange(Flag::RETIRED, Flag::UNPIN).is_ok())
+                .take(n)
+                // a claimed flag is no longer retired, so `prune_retired`
+                // keeps it past the guard
+                .map(|flag| unsafe { &*(flag as *const AtomicFlag<N>) })
+                .collect(),
+            None => Vec::new(),
         };
-        local
+        let batch: AtomicStack<AtomicFlag<N>> =
+            (flags.len()..n).map(|_| Default::default()).collect();
+        let stack_guard = batch.try_own().expect("batch is not shared yet");
+        let fresh: Vec<*const AtomicFlag<N>> = batch
+            .iter(&stack_guard)
+            .map(|flag| flag as *const _)
+            .collect();
+        drop(stack_guard);
+        self.flags.append(batch);
+        // appending moves the nodes as they are, and flags are never popped
+        flags.extend(fresh.into_iter().map(|flag| unsafe { &*flag }));
+
+        self.counters.registered.fetch_add(n, Ordering::Relaxed);
+        flags.into_iter().map(|flag| self.local(flag)).collect()
+    }
+    fn local<'a>(&'a self, flag: &'a AtomicFlag<N>) -> Local<'a, T, CAP, N, R> {
+        Local {
+            bag: Default::default(),
+            spare: Cell::new(Some(Default::default())),
+            flag,
+            depth: Cell::new(0),
+            backoff: Default::default(),
+            global: self,
+        }
     }
+    fn reuse_flag(&self) -> Option<&AtomicFlag<N>> {
+        let stack_guard = self.flags.try_own()?;
+        let flag = self
+            .flags
+            .iter(&stack_guard)
+            .find(|flag| flag.compare_exchange(Flag::RETIRED, Flag::UNPIN).is_ok())?;
+        // a claimed flag is no longer retired, so `prune_retired` keeps it
+        // past the guard
+        Some(unsafe { &*(flag as *const AtomicFlag<N>) })
+    }
+    /// Hand `bag` over, returning an emptied bag the advance attempted
+    /// afterwards reclaimed, for the migrating [`Local`] to reuse.
     #[cold]
-    unsafe fn migrate(&self, guard: &PinGuard, bag: Bag<T, CAP>) {
-        self.bags[guard.epoch as usize].push(bag);
+    unsafe fn migrate(
+        &self,
+        guard: &PinGuard<T, CAP, N, R>,
+        bag: Box<Bag<T, CAP>>,
+        backoff: &Backoff,
+    ) -> Option<Box<Bag<T, CAP>>> {
+        let len = bag.len();
+        self.counters.migrated.fetch_add(len, Ordering::Relaxed);
+        let pending = self.counters.pending.fetch_add(len, Ordering::Relaxed);
+        // the guard's epoch may be one behind, a participant pinned in the
+        // live epoch may still reach the garbage
+        let epoch = self.epoch.load(Ordering::Acquire);
+        debug_assert!(guard.epoch.distance(epoch) <= 1);
+        // already boxed, so only the node may allocate
+        self.bags[epoch.value()].boxed_push(bag);
 
+        if let Some(high_water) = &self.high_water {
+            if pending < high_water.mark && pending + len >= high_water.mark {
+                // the flags may be owned by this very thread, e.g. when a
+                // deferred closure migrates during an advance
+                let pinned = self
+                    .flags
+                    .try_own()
+                    .map(|stack_guard| self.pinned(&stack_guard));
+                (high_water.hook)(&self.snapshot(pinned.unwrap_or(0)));
+            }
+        }
+        // after too many lost or skipped attempts the next migrator waits
+        // for its turn, so garbage can't pile up behind an unlucky thread
+        let starved = self.stalled.load(Ordering::Relaxed) >= STARVATION_LIMIT;
+        if backoff.skip.get() > 0 && !starved {
+            backoff.skip.set(backoff.skip.get() - 1);
+            self.stalled.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let stack_guard = match starved {
+            true => self.own_flags_patiently(),
+            false => self.flags.try_own(),
+        };
+        let mut spare = None;
+        match self.advance_owned(stack_guard, usize::MAX, &mut spare) {
+            Err(Stall::Contended) => {
+                self.stalled.fetch_add(1, Ordering::Relaxed);
+                let step = (backoff.step.get() + 1).min(self.backoff_limit);
+                backoff.step.set(step);
+                backoff.skip.set((1 << step) - 1);
+            }
+            _ => backoff.step.set(0),
+        }
+        spare
+    }
+    /// Advance the global epoch unless some participant is still pinned in
+    /// the previous one, reclaiming the garbage that becomes unreachable.
+    ///
+    /// Returns whether the epoch advanced.
+    pub fn try_advance(&self) -> bool {
+        self.advance_with_budget(usize::MAX).is_ok()
+    }
+    /// Like [`Global::try_advance`], but reclaim at most `max_bags` bags. The
+    /// epoch only advances once the stale bags are all reclaimed, the rest is
+    /// left to later calls.
+    ///
+    /// Returns how many bags were reclaimed.
+    pub fn collect_with_budget(&self, max_bags: usize) -> usize {
+        self.advance_with_budget(max_bags).unwrap_or(0)
+    }
+    fn advance_with_budget(&self, max_bags: usize) -> Result<usize, Stall> {
+        self.advance_owned(self.flags.try_own(), max_bags, &mut None)
+    }
+    /// Advance holding `stack_guard` over the flags, `None` if it couldn't
+    /// be taken. The first reclaimed bag is kept in `spare` if it is empty.
+    fn advance_owned(
+        &self,
+        stack_guard: Option<StackGuard<'_, AtomicFlag<N>>>,
+        max_bags: usize,
+        spare: &mut Option<Box<Bag<T, CAP>>>,
+    ) -> Result<usize, Stall> {
+        let stack_guard = stack_guard.ok_or(Stall::Contended)?;
+        // only owners of `flags` advance, so the epoch is up to date
         let epoch = self.epoch.load(Ordering::Relaxed);
         fence(Ordering::SeqCst);
 
-        if let Some(stack_guard) = self.flags.try_own() {
-            for flag in self.flags.into_iter(&stack_guard) {
-                if flag.load(Ordering::Acquire) == Flag::from_epoch(epoch.decrease()) {
-                    return;
-                }
+        if self.blocked(&stack_guard, epoch) {
+            return Err(Stall::Blocked);
+        }
+        // the oldest bag, retired `N - 2` epochs ago; only one thread can own
+        // `flags`, so bags have a single popper
+        let grabages = &self.bags[epoch.increase().increase().value()];
+        let mut reclaimed = 0;
+        while reclaimed < max_bags {
+            let Some(mut bag) = (unsafe { grabages.boxed_pop() }) else {
+                break;
+            };
+            self.reclaim_bag(&mut bag);
+            if spare.is_none() {
+                *spare = Some(bag);
+            }
+            reclaimed += 1;
+        }
+        self.counters
+            .reclaimed_bags
+            .fetch_add(reclaimed, Ordering::Relaxed);
+
+        if reclaimed == max_bags && !grabages.is_empty() {
+            return Ok(reclaimed);
+        }
+        fence(Ordering::Acquire);
+        self.epoch.store(epoch.increase(), Ordering::Release);
+        self.counters.advances.fetch_add(1, Ordering::Relaxed);
+        self.stalled.store(0, Ordering::Relaxed);
+        Ok(reclaimed)
+    }
+    /// Whether a participant is pinned in the epoch before `epoch`, holding
+    /// back both the advance and the reclamation of the oldest bags.
+    fn blocked(&self, stack_guard: &StackGuard<'_, AtomicFlag<N>>, epoch: Epoch<N>) -> bool {
+        self.flags
+            .iter(stack_guard)
+            .any(|flag| flag.blocks_epoch(epoch))
+    }
+    /// Take a non-empty bag that is already safe to reclaim, so a helper
+    /// thread can drain it instead of the next participant advancing.
+    ///
+    /// Only the bags the next advance would reclaim are stolen, and none
+    /// while some participant still holds that advance back. The garbage is
+    /// handed over as is, bypassing the [`Reclaim`].
+    ///
+    /// Returns `None` if there is no such bag or another thread owns the
+    /// flags.
+    pub fn steal_bag(&self) -> Option<Bag<T, CAP>> {
+        let stack_guard = self.flags.try_own()?;
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        if self.blocked(&stack_guard, epoch) {
+            return None;
+        }
+        let bags = &self.bags[epoch.increase().increase().value()];
+        // single popper since `flags` is owned
+        while let Some(bag) = unsafe { bags.boxed_pop() } {
+            self.counters.reclaimed_bags.fetch_add(1, Ordering::Relaxed);
+            if !bag.is_empty() {
+                self.counters
+                    .pending
+                    .fetch_sub(bag.len(), Ordering::Relaxed);
+                return Some(*bag);
+            }
+        }
+        None
+    }
+    /// Reclaim every global bag regardless of epoch, e.g. on shutdown.
+    ///
+    /// Returns how many bags were reclaimed.
+    ///
+    /// # Panics
+    ///
+    /// If some participant is pinned, it may still reach the garbage.
+    pub fn collect_all(&self) -> usize {
+        let stack_guard = self.own_flags();
+        assert!(
+            self.flags
+                .iter(&stack_guard)
+                .all(|flag| flag.load(Ordering::Acquire).as_epoch().is_none()),
+            "collecting everything while a participant is pinned"
+        );
+        let mut reclaimed = 0;
+        for bags in &self.bags {
+            // single popper since `flags` is owned
+            while let Some(mut bag) = unsafe { bags.boxed_pop() } {
+                self.reclaim_bag(&mut bag);
+                reclaimed += 1;
+            }
+        }
+        self.counters
+            .reclaimed_bags
+            .fetch_add(reclaimed, Ordering::Relaxed);
+        reclaimed
+    }
+    /// Objects and deferred closures waiting in the global bags.
+    pub fn pending_garbage(&self) -> usize {
+        // bags are only popped by whoever owns `flags`
+        let _flags_guard = self.own_flags();
+        self.bags
+            .iter()
+            .map(|bags| {
+                let stack_guard = bags.try_own().expect("bags are only owned here");
+                bags.iter(&stack_guard).map(Bag::len).sum::<usize>()
+            })
+            .sum()
+    }
+    /// Free the flags of dropped [`Local`]s rather than keeping them for
+    /// reuse, so later scans get shorter.
+    ///
+    /// Returns how many flags were freed.
+    pub fn prune_retired(&self) -> usize {
+        let stack_guard = self.own_flags();
+        let mut pruned = 0;
+        // no `Local` refers to a retired flag, and reusing one needs `flags`
+        // owned as well
+        unsafe {
+            self.flags.retain(&stack_guard, |flag| {
+                let retired = flag.load(Ordering::Acquire) == Flag::RETIRED;
+                pruned += retired as usize;
+                !retired
+            })
+        };
+        pruned
+    }
+    /// Wait a while for the flags, but not forever: deferred closures and
+    /// [`Reclaim`] run while the flags are owned, and may migrate themselves.
+    fn own_flags_patiently(&self) -> Option<StackGuard<'_, AtomicFlag<N>>> {
+        let mut backoff = sync::Backoff::default();
+        for _ in 0..STARVED_ATTEMPTS {
+            if let Some(stack_guard) = self.flags.try_own() {
+                return Some(stack_guard);
             }
-            let grabages = &self.bags[epoch.decrease() as usize];
-            while grabages.boxed_pop().is_some() {}
+            backoff.snooze();
+        }
+        None
+    }
+    fn own_flags(&self) -> StackGuard<'_, AtomicFlag<N>> {
+        self.flags.own()
+    }
+    /// Reclaim the garbage of `bag`, leaving it empty for reuse.
+    fn reclaim_bag(&self, bag: &mut Bag<T, CAP>) {
+        self.counters
+            .pending
+            .fetch_sub(bag.len(), Ordering::Relaxed);
+        for garbage in bag.drain() {
+            self.reclaim.reclaim(*garbage);
+        }
+        bag.run_deferred();
+    }
+    /// Assert what must hold whenever no thread advances the epoch, waiting
+    /// for the flags to make sure of it. A no-op without debug assertions.
+    ///
+    /// - every advance moved the epoch by one,
+    /// - the bags of the epoch after the live one are empty: the last
+    ///   advance reclaimed them, and migrations only reach the live and the
+    ///   previous epoch.
+    #[cfg(any(test, feature = "invariants"))]
+    pub fn check_invariants(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        let _stack_guard = self.own_flags();
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        let advances = self.counters.advances.load(Ordering::Relaxed);
+        assert_eq!(
+            advances % N,
+            epoch.value(),
+            "epoch out of step with advances"
+        );
+        let reclaimed = &self.bags[epoch.increase().value()];
+        let stack_guard = reclaimed.try_own().expect("bags are only owned here");
+        assert!(
+            reclaimed.iter(&stack_guard).all(Bag::is_empty),
+            "garbage left behind by the last advance"
+        );
+    }
+    /// Reclaim every bag filed under `epoch`, without waiting for the
+    /// advances that would make it safe, so tests can free garbage at a
+    /// known point.
+    ///
+    /// Returns how many bags were reclaimed.
+    ///
+    /// # Safety
+    ///
+    /// No participant may still reach the garbage filed under `epoch`.
+    #[cfg(any(test, feature = "invariants"))]
+    pub unsafe fn drain_epoch(&self, epoch: Epoch<N>) -> usize {
+        let _stack_guard = self.own_flags();
+        let bags = &self.bags[epoch.value()];
+        let mut reclaimed = 0;
+        // single popper since `flags` is owned
+        while let Some(mut bag) = unsafe { bags.boxed_pop() } {
+            self.reclaim_bag(&mut bag);
+            reclaimed += 1;
+        }
+        self.counters
+            .reclaimed_bags
+            .fetch_add(reclaimed, Ordering::Relaxed);
+        reclaimed
+    }
+    /// The live global epoch.
+    pub fn epoch(&self) -> Epoch<N> {
+        self.epoch.load(Ordering::Acquire)
+    }
+    /// Participants that are registered and not dropped yet, counted from
+    /// their flags rather than from [`Stats::registered`].
+    pub fn registered_count(&self) -> usize {
+        let stack_guard = self.own_flags();
+        self.flags
+            .iter(&stack_guard)
+            .filter(|flag| flag.load(Ordering::Relaxed) != Flag::RETIRED)
+            .count()
+    }
+    /// How many participants are pinned at each epoch, indexed by
+    /// [`Epoch::value`]. Those pinned at the epoch before the live one are
+    /// what holds back the next advance.
+    pub fn pin_summary(&self) -> [usize; N] {
+        let stack_guard = self.own_flags();
+        let mut summary = [0; N];
+        for flag in self.flags.iter(&stack_guard) {
+            if let Some(epoch) = flag.load(Ordering::Relaxed).as_epoch() {
+                summary[epoch.value()] += 1;
+            }
+        }
+        summary
+    }
+    /// Read the counters, only meant for debugging since they are not
+    /// synchronized with each other.
+    pub fn stats(&self) -> Stats {
+        let stack_guard = self.own_flags();
+        self.snapshot(self.pinned(&stack_guard))
+    }
+    fn pinned(&self, stack_guard: &StackGuard<'_, AtomicFlag<N>>) -> usize {
+        self.flags
+            .iter(stack_guard)
+            .filter(|flag| flag.load(Ordering::Relaxed).as_epoch().is_some())
+            .count()
+    }
+    fn snapshot(&self, pinned: usize) -> Stats {
+        Stats {
+            migrated: self.counters.migrated.load(Ordering::Relaxed),
+            advances: self.counters.advances.load(Ordering::Relaxed),
+            reclaimed_bags: self.counters.reclaimed_bags.load(Ordering::Relaxed),
+            pending: self.counters.pending.load(Ordering::Relaxed),
+            registered: self.counters.registered.load(Ordering::Relaxed),
+            pinned,
+        }
+    }
+}
 
-            fence(Ordering::Acquire);
-            self.epoch.store(epoch.increase(), Ordering::Release);
+impl<T> Global<T> {
+    /// Configure a `Global` step by step, starting from the defaults.
+    pub fn builder() -> GlobalBuilder<T> {
+        GlobalBuilder {
+            reclaim: DropReclaim,
+            backoff_limit: DEFAULT_BACKOFF_LIMIT,
+            bags_per_epoch: 0,
+            high_water: None,
+            _marker: PhantomData,
         }
     }
 }
 
-pub struct PinGuard<'a> {
-    epoch: Epoch,
-    flag: &'a AtomicFlag,
+/// Configuration of a [`Global`], see [`Global::builder`].
+///
+/// The bag capacity and the epoch count are const generics of the `Global`,
+/// so their setters change the builder's type.
+#[derive(Debug)]
+pub struct GlobalBuilder<T, const CAP: usize = 128, const N: usize = 3, R = DropReclaim> {
+    reclaim: R,
+    backoff_limit: u32,
+    bags_per_epoch: usize,
+    high_water: Option<HighWater>,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T, const CAP: usize, const N: usize, R> GlobalBuilder<T, CAP, N, R> {
+    /// Bag length that triggers a migration.
+    pub fn cap<const C: usize>(self) -> GlobalBuilder<T, C, N, R> {
+        GlobalBuilder {
+            reclaim: self.reclaim,
+            backoff_limit: self.backoff_limit,
+            bags_per_epoch: self.bags_per_epoch,
+            high_water: self.high_water,
+            _marker: PhantomData,
+        }
+    }
+    /// Number of epochs garbage is spread over, see [`Epoch`].
+    pub fn epochs<const M: usize>(self) -> GlobalBuilder<T, CAP, M, R> {
+        const { assert!(M >= 3, "epoch count must be at least 3") };
+        GlobalBuilder {
+            reclaim: self.reclaim,
+            backoff_limit: self.backoff_limit,
+            bags_per_epoch: self.bags_per_epoch,
+            high_water: self.high_water,
+            _marker: PhantomData,
+        }
+    }
+    pub fn reclaim<S: Reclaim<T>>(self, reclaim: S) -> GlobalBuilder<T, CAP, N, S> {
+        GlobalBuilder {
+            reclaim,
+            backoff_limit: self.backoff_limit,
+            bags_per_epoch: self.bags_per_epoch,
+            high_water: self.high_water,
+            _marker: PhantomData,
+        }
+    }
+    /// See [`Global::set_backoff_limit`].
+    pub fn backoff_limit(mut self, limit: u32) -> Self {
+        self.backoff_limit = limit;
+        self
+    }
+    /// See [`Global::preallocate`].
+    pub fn preallocate(mut self, bags_per_epoch: usize) -> Self {
+        self.bags_per_epoch = bags_per_epoch;
+        self
+    }
+    /// See [`Global::on_migrate`].
+    pub fn on_migrate(
+        mut self,
+        high_water: usize,
+        hook: impl Fn(&Stats) + Send + Sync + 'static,
+    ) -> Self {
+        self.high_water = Some(HighWater {
+            mark: high_water,
+            hook: Box::new(hook),
+        });
+        self
+    }
 }
 
-impl<'a> Drop for PinGuard<'a> {
+impl<T, const CAP: usize, const N: usize, R: Reclaim<T>> GlobalBuilder<T, CAP, N, R> {
+    /// # Panics
+    ///
+    /// See [`Global::set_backoff_limit`].
+    pub fn build(self) -> Global<T, CAP, N, R> {
+        let mut global = Global::with_reclaim(self.reclaim);
+        global.set_backoff_limit(self.backoff_limit);
+        if self.bags_per_epoch > 0 {
+            global.preallocate(self.bags_per_epoch);
+        }
+        global.high_water = self.high_water;
+        global
+    }
+}
+
+/// Keeps its [`Local`] pinned, nested guards share the outermost one's epoch.
+///
+/// The pin belongs to the thread that took it, so the guard is neither `Send`
+/// nor `Sync`:
+///
+/// ```compile_fail
+/// use atomic::gc::prelude::*;
+///
+/// let global: Global<usize> = Global::default();
+/// let local = global.register();
+/// let guard = local.pin();
+/// std::thread::scope(|s| {
+///     s.spawn(move || drop(guard));
+/// });
+/// ```
+///
+/// Garbage retired through [`PinGuard::defer`] goes into the bag of the
+/// participant the guard pins, there is no other participant to pass by
+/// mistake. [`Local::migrate`] panics if handed a guard of another one.
+pub struct PinGuard<'a, T, const CAP: usize = 128, const N: usize = 3, R: Reclaim<T> = DropReclaim>
+{
+    epoch: Epoch<N>,
+    local: &'a Local<'a, T, CAP, N, R>,
+}
+
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> PinGuard<'a, T, CAP, N, R> {
+    /// The epoch the participant pinned in.
+    pub fn epoch(&self) -> Epoch<N> {
+        self.epoch
+    }
+    /// Retire `garbage`, already unreachable for participants pinning
+    /// later.
+    ///
+    /// It is handed to the `Global`'s [`Reclaim`], dropping it by default,
+    /// exactly once: after two epoch advances past the migration of its bag,
+    /// which happens when the bag is full, on [`Local::flush`] or when the
+    /// `Local` drops. Garbage still pending when the `Global` drops is
+    /// reclaimed then.
+    pub fn defer(&self, garbage: Box<T>) {
+        self.local.fill_bag(self, |bag| bag.push(garbage));
+    }
+}
+
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> fmt::Debug
+    for PinGuard<'a, T, CAP, N, R>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinGuard")
+            .field("epoch", &self.epoch)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Only the outermost guard unpins.
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> Drop for PinGuard<'a, T, CAP, N, R> {
     fn drop(&mut self) {
-        self.flag.store(Flag::Unpin, Ordering::Relaxed);
+        let depth = self.local.depth.get() - 1;
+        self.local.depth.set(depth);
+        if depth == 0 {
+            // reads made while pinned must not move past the unpin, or an
+            // advance seeing it could reclaim what is still being read
+            self.local.flag.store(Flag::UNPIN, Ordering::Release);
+        }
     }
 }
 
-pub struct Local<'a, T, const CAP: usize> {
-    bag: Cell<Bag<T, CAP>>,
-    flag: &'a AtomicFlag,
-    global: &'a Global<T, CAP>,
+pub struct Local<'a, T, const CAP: usize = 128, const N: usize = 3, R: Reclaim<T> = DropReclaim> {
+    bag: Cell<Box<Bag<T, CAP>>>,
+    /// Empty bag swapped in when `bag` migrates.
+    spare: Cell<Option<Box<Bag<T, CAP>>>>,
+    flag: &'a AtomicFlag<N>,
+    /// Number of live [`PinGuard`]s.
+    depth: Cell<usize>,
+    backoff: Backoff,
+    global: &'a Global<T, CAP, N, R>,
 }
 
-impl<'a, T, const CAP: usize> Local<'a, T, CAP> {
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> Local<'a, T, CAP, N, R> {
+    /// Pin the participant, or return a nested guard if it already is.
+    #[inline]
+    pub fn pin(&self) -> PinGuard<'_, T, CAP, N, R> {
+        let depth = self.depth.get();
+        self.depth.set(depth + 1);
+        let epoch = if depth > 0 {
+            let flag = self.flag.load(Ordering::Relaxed);
+            flag.as_epoch().expect("Local was expected to be pinned")
+        } else {
+            debug_assert_eq!(
+                self.flag.load(Ordering::Relaxed),
+                Flag::UNPIN,
+                "Local was expected to be unpinned"
+            );
+            // a scan that ran between loading the epoch and publishing the
+            // flag did not see it, e.g. a flag registered mid-scan, so retry
+            // until the published epoch is still the live one
+            let mut epoch = self.global.epoch.load(Ordering::Relaxed);
+            loop {
+                self.flag.publish(Flag::from_epoch(epoch));
+                let live = self.global.epoch.load(Ordering::Relaxed);
+                if live == epoch {
+                    break epoch;
+                }
+                epoch = live;
+            }
+        };
+
+        PinGuard { epoch, local: self }
+    }
+    pub fn is_pinned(&self) -> bool {
+        self.flag.load(Ordering::Relaxed).as_epoch().is_some()
+    }
+    /// Unpin and pin again at the current epoch, letting the global epoch
+    /// advance during long loops.
+    ///
+    /// Anything loaded under the old guard, e.g. a
+    /// [`Shared`](super::pointer::Shared), must not be used afterwards. A
+    /// nested guard stays pinned at the outermost guard's epoch.
+    ///
+    /// If the global epoch is still the guard's, the guard is returned as is:
+    /// its flag stays published, so neither a store nor a fence is needed.
+    pub fn repin<'g>(&'g self, guard: PinGuard<'g, T, CAP, N, R>) -> PinGuard<'g, T, CAP, N, R> {
+        // a stale epoch only keeps the participant pinned a little longer
+        if self.global.epoch.load(Ordering::Relaxed) == guard.epoch {
+            return guard;
+        }
+        drop(guard);
+        self.pin()
+    }
+    /// Objects and deferred closures in the local bag.
+    pub fn bag_len(&self) -> usize {
+        unsafe { &*self.bag.as_ptr() }.len()
+    }
+    /// Bag length that triggers a migration, i.e. `CAP`.
+    pub const fn capacity(&self) -> usize {
+        CAP
+    }
+    /// Pin only for retiring `garbage`, for callers that hold no guard.
+    /// Nests in the guard already held, if any.
+    pub fn pin_and_defer(&self, garbage: Box<T>) {
+        self.pin().defer(garbage);
+    }
+    /// Retire `garbage`, see [`PinGuard::defer`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` was pinned by another participant.
+    pub fn migrate(&self, guard: &PinGuard<T, CAP, N, R>, garbage: Box<T>) {
+        self.check_guard(guard);
+        guard.defer(garbage);
+    }
+    /// Retire every box of `garbage` like [`Local::migrate`], migrating
+    /// whenever the bag fills up along the way.
+    pub fn migrate_many(
+        &self,
+        guard: &PinGuard<T, CAP, N, R>,
+        garbage: impl IntoIterator<Item = Box<T>>,
+    ) {
+        self.check_guard(guard);
+        let mut garbage = garbage.into_iter();
+        loop {
+            let full = {
+                let bag = unsafe { &mut *self.bag.as_ptr() };
+                for value in garbage.by_ref() {
+                    bag.push(value);
+                    if bag.is_full() {
+                        break;
+                    }
+                }
+                bag.is_full().then(|| self.swap_spare(bag))
+            };
+            match full {
+                Some(old) => self.hand_over(guard, old),
+                None => break,
+            }
+        }
+    }
+    /// Collect retirements while pinned by `guard`, handing them to the bag
+    /// all at once when the returned guard drops.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `guard` was pinned by another participant.
+    pub fn reclaim_guard<'g>(
+        &'g self,
+        guard: &'g PinGuard<'g, T, CAP, N, R>,
+    ) -> ReclaimGuard<'g, T, CAP, N, R> {
+        self.check_guard(guard);
+        ReclaimGuard {
+            local: self,
+            guard,
+            garbage: Vec::new(),
+        }
+    }
+    /// Run `f` once no pinned participant can observe anything retired
+    /// before it, i.e. after the global epoch has moved past `guard`'s.
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, guard: &PinGuard<T, CAP, N, R>, f: F) {
+        self.check_guard(guard);
+        self.fill_bag(guard, |bag| bag.defer(Box::new(f)));
+    }
+    /// Hand the pending bag to the global collector regardless of how full
+    /// it is, e.g. before the thread parks or shuts down.
+    pub fn flush(&self, guard: &PinGuard<T, CAP, N, R>) {
+        self.check_guard(guard);
+        let bag = self.swap_spare(unsafe { &mut *self.bag.as_ptr() });
+        self.hand_over(guard, bag);
+    }
+    /// A guard pins exactly one participant, see [`PinGuard::defer`] for
+    /// retiring garbage without a separate guard argument.
     #[inline]
-    pub fn pin(&'a self) -> PinGuard<'a> {
-        debug_assert_eq!(
-            self.flag.load(Ordering::Relaxed),
-            Flag::Unpin,
-            "Local was expected to be unpinned"
+    fn check_guard(&self, guard: &PinGuard<T, CAP, N, R>) {
+        assert!(
+            ptr::eq(guard.local, self),
+            "guard pinned by another participant"
         );
-        let epoch = self.global.epoch.load(Ordering::Relaxed);
+    }
+    fn fill_bag(&self, guard: &PinGuard<T, CAP, N, R>, fill: impl FnOnce(&mut Bag<T, CAP>)) {
+        let full = {
+            let bag = unsafe { &mut *self.bag.as_ptr() };
+            fill(bag);
+            bag.is_full().then(|| self.swap_spare(bag))
+        };
+        // the bag is no longer borrowed, a migrate hook may come back here
+        if let Some(old) = full {
+            self.hand_over(guard, old);
+        }
+    }
+    fn swap_spare(&self, bag: &mut Box<Bag<T, CAP>>) -> Box<Bag<T, CAP>> {
+        mem::replace(bag, self.spare.take().unwrap_or_default())
+    }
+    /// Migrate `bag`, keeping whatever emptied bag comes back as the spare.
+    fn hand_over(&self, guard: &PinGuard<T, CAP, N, R>, bag: Box<Bag<T, CAP>>) {
+        if let Some(spare) = unsafe { self.global.migrate(guard, bag, &self.backoff) } {
+            self.spare.set(Some(spare));
+        }
+    }
+}
 
-        self.flag.store(Flag::from_epoch(epoch), Ordering::SeqCst);
-        fence(Ordering::SeqCst);
+/// Batch of retirements, see [`Local::reclaim_guard`].
+pub struct ReclaimGuard<'g, T, const CAP: usize, const N: usize, R: Reclaim<T>> {
+    local: &'g Local<'g, T, CAP, N, R>,
+    guard: &'g PinGuard<'g, T, CAP, N, R>,
+    garbage: Vec<Box<T>>,
+}
 
-        PinGuard {
-            epoch,
-            flag: &self.flag,
-        }
+impl<'g, T, const CAP: usize, const N: usize, R: Reclaim<T>> ReclaimGuard<'g, T, CAP, N, R> {
+    /// Retire `garbage` once the batch drops, see [`Local::migrate`].
+    pub fn retire(&mut self, garbage: Box<T>) {
+        self.garbage.push(garbage);
+    }
+    pub fn len(&self) -> usize {
+        self.garbage.len()
     }
-    pub fn migrate(&self, guard: &PinGuard, garbage: Box<T>) {
-        let bag = unsafe { &mut *self.bag.as_ptr() };
+    pub fn is_empty(&self) -> bool {
+        self.garbage.is_empty()
+    }
+}
 
-        bag.push(garbage);
-        if bag.is_full() {
-            let mut old = Bag::default();
-            mem::swap(&mut old, bag);
-            unsafe {
-                self.global.migrate(guard, old);
-            }
+impl<'g, T, const CAP: usize, const N: usize, R: Reclaim<T>> fmt::Debug
+    for ReclaimGuard<'g, T, CAP, N, R>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReclaimGuard")
+            .field("guard", self.guard)
+            .field("len", &self.garbage.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'g, T, const CAP: usize, const N: usize, R: Reclaim<T>> Drop
+    for ReclaimGuard<'g, T, CAP, N, R>
+{
+    fn drop(&mut self) {
+        self.local
+            .migrate_many(self.guard, mem::take(&mut self.garbage));
+    }
+}
+
+/// Hands leftover garbage to the global bags and retires the flag so a later
+/// [`Global::register`] can reuse it.
+impl<'a, T, const CAP: usize, const N: usize, R: Reclaim<T>> Drop for Local<'a, T, CAP, N, R> {
+    fn drop(&mut self) {
+        if !self.bag.get_mut().is_empty() {
+            let spare = self.spare.get_mut().take().unwrap_or_default();
+            let bag = mem::replace(self.bag.get_mut(), spare);
+            let guard = self.pin();
+            unsafe { self.global.migrate(&guard, bag, &self.backoff) };
         }
+        self.flag.store(Flag::RETIRED, Ordering::Release);
+        self.global
+            .counters
+            .registered
+            .fetch_sub(1, Ordering::Relaxed);
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(loom)))]
 pub mod test {
-    use std::{sync::Mutex, thread};
+    use std::{
+        cell::Cell,
+        ptr,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+    };
 
-    use super::Global;
+    use super::{Bag, Global, Local, Reclaim, Stats, STARVATION_LIMIT};
+    use crate::{gc::epoch::Flag, test_alloc};
+
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
 
+    #[test]
+    fn gc_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        fn assert_send<T: Send>() {}
+        assert_send_sync::<Global<usize>>();
+        assert_send_sync::<Global<Cell<usize>>>();
+        assert_send::<Local<'_, usize, 128>>();
+    }
     #[test]
     fn gc_one() {
         let global: Global<usize, 1> = Global::default();
@@ -164,7 +1148,797 @@ pub mod test {
         });
     }
     #[test]
-    #[ignore = "datarace"]
+    fn gc_invariants() {
+        fn check<const N: usize>() {
+            let global: Global<usize, 2, N> = Global::default();
+            let done = AtomicBool::new(false);
+            thread::scope(|s| {
+                let workers: Vec<_> = (0..4)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let local = global.register();
+                            for i in 0..2000 {
+                                let guard = local.pin();
+                                guard.defer(Box::new(i));
+                                if i % 100 == 0 {
+                                    global.collect_with_budget(1);
+                                }
+                            }
+                        })
+                    })
+                    .collect();
+                s.spawn(|| {
+                    while !done.load(Ordering::Acquire) {
+                        global.check_invariants();
+                    }
+                });
+                for worker in workers {
+                    worker.join().unwrap();
+                }
+                done.store(true, Ordering::Release);
+            });
+            global.check_invariants();
+            while global.try_advance() && global.stats().pending > 0 {
+                global.check_invariants();
+            }
+        }
+        check::<3>();
+        check::<4>();
+        check::<5>();
+    }
+    #[test]
+    fn gc_reuse_flag() {
+        let global: Global<usize, 4> = Global::default();
+        for i in 0..100 {
+            let local = global.register();
+            let guard = local.pin();
+            guard.defer(Box::new(i));
+        }
+        assert_eq!(global.flags.len(), 1);
+
+        let locals: Vec<_> = (0..4).map(|_| global.register()).collect();
+        assert_eq!(global.flags.len(), 4);
+        drop(locals);
+        let _local = global.register();
+        assert_eq!(global.flags.len(), 4);
+    }
+    #[test]
+    fn gc_prune_retired() {
+        let global: Global<usize> = Global::default();
+        let mut locals: Vec<_> = (0..4).map(|_| global.register()).collect();
+        let pinned = locals.remove(1);
+        let guard = pinned.pin();
+        drop(locals);
+        assert_eq!(global.prune_retired(), 3);
+        assert_eq!(global.flags.len(), 1);
+        assert_eq!(global.prune_retired(), 0);
+
+        // the live pinned participant still blocks the second advance
+        assert!(global.try_advance());
+        assert!(!global.try_advance());
+        drop(guard);
+        assert!(global.try_advance());
+
+        drop(pinned);
+        let _local = global.register();
+        assert_eq!(global.flags.len(), 1);
+    }
+    #[test]
+    fn gc_register_many() {
+        let global: Global<usize> = Global::default();
+        drop(global.register_many(3));
+        let locals = global.register_many(32);
+        assert_eq!(locals.len(), 32);
+        assert_eq!(global.flags.len(), 32);
+        assert_eq!(global.stats().registered, 32);
+
+        for (i, local) in locals.iter().enumerate() {
+            assert!(locals[..i]
+                .iter()
+                .all(|other| !ptr::eq(other.flag, local.flag)));
+        }
+        let guards: Vec<_> = locals.iter().step_by(2).map(Local::pin).collect();
+        for (i, local) in locals.iter().enumerate() {
+            assert_eq!(local.is_pinned(), i % 2 == 0);
+        }
+        assert_eq!(global.stats().pinned, 16);
+        drop(guards);
+    }
+    #[test]
+    fn gc_pin_and_defer() {
+        let global: Global<DropFlag, 8> = Global::default();
+        let local = global.register();
+        let dropped: Vec<_> = (0..100).map(|_| Arc::new(AtomicBool::new(false))).collect();
+
+        for flag in &dropped {
+            local.pin_and_defer(Box::new(DropFlag(flag.clone())));
+        }
+        assert!(!local.is_pinned());
+        local.flush(&local.pin());
+        for _ in 0..3 {
+            assert!(global.try_advance());
+        }
+        assert!(dropped.iter().all(|flag| flag.load(Ordering::Relaxed)));
+    }
+    #[test]
+    fn gc_drop_once() {
+        struct Counted(Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let global: Global<Counted, 4> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        for _ in 0..10 {
+            guard.defer(Box::new(Counted(dropped.clone())));
+        }
+        local.flush(&guard);
+        drop(guard);
+
+        assert!(global.try_advance());
+        assert!(global.try_advance());
+        assert_eq!(dropped.load(Ordering::Relaxed), 10);
+        for _ in 0..3 {
+            global.try_advance();
+        }
+        drop(local);
+        drop(global);
+        assert_eq!(dropped.load(Ordering::Relaxed), 10);
+    }
+    #[test]
+    fn gc_migrate_many() {
+        let global: Global<usize, 128> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        local.migrate_many(&guard, (0..300).map(Box::new));
+        assert_eq!(global.stats().migrated, 256);
+        assert_eq!(local.bag_len(), 44);
+
+        local.migrate_many(&guard, (0..84).map(Box::new));
+        assert_eq!(global.stats().migrated, 384);
+        assert_eq!(local.bag_len(), 0);
+        local.migrate_many(&guard, []);
+        assert_eq!(global.stats().migrated, 384);
+    }
+    #[test]
+    fn gc_reclaim_guard() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        let mut batch = local.reclaim_guard(&guard);
+        for i in 0..10 {
+            batch.retire(Box::new(i));
+        }
+        assert_eq!((batch.len(), local.bag_len()), (10, 0));
+        drop(batch);
+        assert_eq!(local.bag_len(), 10);
+    }
+    #[test]
+    fn gc_defer() {
+        let global: Global<usize, 1> = Global::default();
+        let a = global.register();
+        let b = global.register();
+        let ran = Arc::new(AtomicBool::new(false));
+
+        let guard_a = a.pin();
+        let flag = ran.clone();
+        a.defer(&guard_a, move || flag.store(true, Ordering::Relaxed));
+        assert!(!ran.load(Ordering::Relaxed));
+
+        // `a` is still pinned at the epoch the closure was deferred in
+        let guard_b = b.pin();
+        guard_b.defer(Box::new(0));
+        assert!(!ran.load(Ordering::Relaxed));
+
+        drop(guard_a);
+        guard_b.defer(Box::new(0));
+        assert!(ran.load(Ordering::Relaxed));
+    }
+    #[test]
+    fn gc_flush() {
+        let global: Global<DropFlag> = Global::default();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let local = global.register();
+
+        let guard = local.pin();
+        guard.defer(Box::new(DropFlag(dropped.clone())));
+        local.flush(&guard);
+        drop(guard);
+        assert!(!dropped.load(Ordering::Relaxed));
+
+        for _ in 0..2 {
+            thread::scope(|s| {
+                s.spawn(|| {
+                    let local = global.register();
+                    let guard = local.pin();
+                    local.flush(&guard);
+                });
+            });
+        }
+        assert!(dropped.load(Ordering::Relaxed));
+    }
+    #[test]
+    fn gc_migrate_live_epoch() {
+        let global: Global<DropFlag> = Global::default();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let (writer, reader) = (global.register(), global.register());
+
+        let guard = writer.pin();
+        assert!(global.try_advance());
+        // pinned in the new epoch, may still see the object
+        let reader_guard = reader.pin();
+        guard.defer(Box::new(DropFlag(dropped.clone())));
+        writer.flush(&guard);
+        drop(guard);
+
+        assert!(global.try_advance());
+        assert!(!dropped.load(Ordering::Relaxed));
+        drop(reader_guard);
+        assert!(global.try_advance());
+        assert!(dropped.load(Ordering::Relaxed));
+    }
+    #[test]
+    fn gc_builder() {
+        #[derive(Default)]
+        struct Counting(AtomicUsize);
+
+        impl Reclaim<usize> for Counting {
+            fn reclaim(&self, _: usize) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let global = Global::<usize>::builder().build();
+        assert_eq!(global.register().capacity(), 128);
+
+        let hooked = Arc::new(AtomicUsize::new(0));
+        let counter = hooked.clone();
+        let global = Global::builder()
+            .cap::<2>()
+            .epochs::<4>()
+            .reclaim(Counting::default())
+            .backoff_limit(0)
+            .preallocate(1)
+            .on_migrate(2, move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .build();
+        let local = global.register();
+        assert_eq!(local.capacity(), 2);
+        for i in 0..2 {
+            local.pin_and_defer(Box::new(i));
+        }
+        assert_eq!(hooked.load(Ordering::Relaxed), 1);
+        // four epochs before wrapping around
+        for _ in 0..4 {
+            assert!(global.try_advance());
+        }
+        assert_eq!(global.epoch().value(), 1);
+        assert_eq!(global.reclaim.0.load(Ordering::Relaxed), 2);
+    }
+    #[test]
+    fn gc_preallocate() {
+        // the bag fills up and migrates on the defer
+        let migrate = |global: &Global<usize, 1>| {
+            let local = global.register();
+            let guard = local.pin();
+            let garbage = Box::new(1);
+            test_alloc::count(|| guard.defer(garbage)).1
+        };
+        assert_eq!(migrate(&Global::default()), 1);
+
+        let mut global = Global::default();
+        global.preallocate(2);
+        assert_eq!(migrate(&global), 0);
+        assert_eq!(global.pending_garbage(), 1);
+    }
+    #[test]
+    fn gc_recycle_bags() {
+        let mut global: Global<usize, 2> = Global::default();
+        global.preallocate(2);
+        let local = global.register();
+        let migrations = (0..101)
+            .map(|i| {
+                let guard = local.pin();
+                let garbage = Box::new(i);
+                test_alloc::count(|| guard.defer(garbage)).1
+            })
+            .collect::<Vec<_>>();
+        // once the first bags come back, migrating reuses them
+        assert!(migrations[6..].iter().all(|&n| n == 0), "{migrations:?}");
+
+        // a dropped `Local` hands its last bag over without allocating another
+        assert_eq!(test_alloc::count(|| drop(local)).1, 0);
+    }
+    #[test]
+    fn gc_bag() {
+        fn check<const CAP: usize>() {
+            let mut bag: Bag<usize, CAP> = Bag::default();
+            assert!(bag.is_empty());
+            for i in 0..CAP {
+                assert!(!bag.is_full());
+                bag.push(Box::new(i));
+                assert_eq!(bag.len(), i + 1);
+            }
+            assert!(bag.is_full());
+            assert!(bag.drain().map(|x| *x).eq(0..CAP));
+            assert!(bag.is_empty());
+            assert_eq!(bag.drain().count(), 0);
+
+            // deferred closures take slots as well
+            bag.defer(Box::new(|| {}));
+            assert_eq!(bag.len(), 1);
+            assert_eq!(bag.is_full(), CAP == 1);
+        }
+        check::<1>();
+        check::<4>();
+        check::<128>();
+    }
+    #[test]
+    fn gc_bag_len() {
+        let global: Global<usize, 4> = Global::default();
+        let local = global.register();
+        assert_eq!(local.capacity(), 4);
+
+        let guard = local.pin();
+        for i in 0..3 {
+            guard.defer(Box::new(i));
+            assert_eq!(local.bag_len(), i + 1);
+        }
+        assert_eq!(global.pending_garbage(), 0);
+        local.defer(&guard, || {});
+        assert_eq!(local.bag_len(), 0);
+        assert_eq!(global.pending_garbage(), 4);
+        guard.defer(Box::new(4));
+        local.flush(&guard);
+        assert_eq!(global.pending_garbage(), 5);
+        drop(guard);
+
+        for _ in 0..3 {
+            global.try_advance();
+        }
+        assert_eq!(global.pending_garbage(), 0);
+    }
+    #[test]
+    fn gc_collect_all() {
+        let global: Global<DropFlag, 1> = Global::default();
+        let local = global.register();
+        let dropped: Vec<_> = (0..3).map(|_| Arc::new(AtomicBool::new(false))).collect();
+
+        for flag in &dropped {
+            let guard = local.pin();
+            guard.defer(Box::new(DropFlag(flag.clone())));
+            drop(guard);
+        }
+        assert!(!dropped.last().unwrap().load(Ordering::Relaxed));
+        assert!(global.pending_garbage() > 0);
+
+        let pending = global.pending_garbage();
+        assert_eq!(global.collect_all(), pending);
+        assert_eq!(global.pending_garbage(), 0);
+        assert!(dropped.iter().all(|flag| flag.load(Ordering::Relaxed)));
+    }
+    #[test]
+    #[should_panic = "pinned"]
+    fn gc_collect_all_pinned() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let _guard = local.pin();
+        global.collect_all();
+    }
+    #[test]
+    fn gc_try_advance() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        for _ in 0..5 {
+            assert!(global.try_advance());
+        }
+
+        let _guard = local.pin();
+        assert!(global.try_advance());
+        assert!(!global.try_advance());
+        assert!(!global.try_advance());
+    }
+    #[test]
+    fn gc_epoch_count() {
+        let global: Global<DropFlag, 1, 4> = Global::default();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let local = global.register();
+
+        let guard = local.pin();
+        guard.defer(Box::new(DropFlag(dropped.clone())));
+        drop(guard);
+        // filed under epoch 0 and advanced once by `migrate`, reclaimed when
+        // advancing out of epoch 2
+        for _ in 0..2 {
+            assert!(!dropped.load(Ordering::Relaxed));
+            assert!(global.try_advance());
+        }
+        assert!(dropped.load(Ordering::Relaxed));
+    }
+    #[test]
+    fn gc_epoch() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        assert_eq!(guard.epoch(), global.epoch());
+        assert!(global.try_advance());
+        assert_ne!(guard.epoch(), global.epoch());
+        assert_eq!(guard.epoch().distance(global.epoch()), 1);
+    }
+    #[test]
+    #[should_panic(expected = "another participant")]
+    fn gc_guard_mismatch() {
+        let global: Global<usize> = Global::default();
+        let (a, b) = (global.register(), global.register());
+        let guard = a.pin();
+        b.migrate(&guard, Box::new(0));
+    }
+    #[test]
+    fn gc_guard_debug() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        assert_eq!(format!("{guard:?}"), "PinGuard { epoch: Epoch(0), .. }");
+        drop(guard);
+        global.try_advance();
+        let guard = local.pin();
+        assert_eq!(format!("{guard:?}"), "PinGuard { epoch: Epoch(1), .. }");
+    }
+    #[test]
+    fn gc_debug() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        guard.defer(Box::new(0));
+        local.flush(&guard);
+        // only counters, the bags are left alone
+        let debug = format!("{global:?}");
+        assert!(debug.starts_with("Global { epoch: "), "{debug}");
+        assert!(debug.contains("pending: 1,"), "{debug}");
+        assert!(debug.ends_with("reclaim: DropReclaim, .. }"), "{debug}");
+        assert_eq!(global.pending_garbage(), 1);
+    }
+    #[test]
+    fn gc_nested_pin() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+
+        let outer = local.pin();
+        let pinned = local.flag.load(Ordering::Relaxed);
+        assert!(global.try_advance());
+        let inner = local.pin();
+        assert_eq!(inner.epoch, outer.epoch);
+        drop(inner);
+        assert_eq!(local.flag.load(Ordering::Relaxed), pinned);
+        assert!(!global.try_advance());
+
+        drop(outer);
+        assert_eq!(local.flag.load(Ordering::Relaxed), Flag::UNPIN);
+        assert!(global.try_advance());
+    }
+    #[test]
+    fn gc_repin() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let advance = || thread::scope(|s| s.spawn(|| global.try_advance()).join().unwrap());
+
+        assert!(!local.is_pinned());
+        let mut guard = local.pin();
+        assert!(local.is_pinned());
+        // same epoch, the guard is kept
+        let epoch = guard.epoch();
+        guard = local.repin(guard);
+        assert_eq!(guard.epoch(), epoch);
+        assert_eq!(local.flag.load(Ordering::Relaxed), Flag::from_epoch(epoch));
+        for _ in 0..5 {
+            assert!(advance());
+            assert!(!advance());
+            guard = local.repin(guard);
+            assert!(local.is_pinned());
+        }
+        drop(guard);
+        assert!(!local.is_pinned());
+    }
+    #[test]
+    fn gc_stats() {
+        let global: Global<usize, 4> = Global::default();
+        let local = global.register();
+        let _other = global.register();
+        assert_eq!(
+            global.stats(),
+            Stats {
+                registered: 2,
+                ..Default::default()
+            }
+        );
+
+        let guard = local.pin();
+        for i in 0..20 {
+            guard.defer(Box::new(i));
+        }
+        local.defer(&guard, || {});
+        let stats = global.stats();
+        assert_eq!(stats.migrated, 20);
+        assert_eq!(stats.pinned, 1);
+        drop(guard);
+
+        for _ in 0..3 {
+            assert!(global.try_advance());
+        }
+        let stats = global.stats();
+        // only the first migration advanced while pinned
+        assert_eq!(stats.advances, 4);
+        assert_eq!(stats.reclaimed_bags, 5);
+        assert_eq!(stats.pending, 0);
+        assert_eq!(stats.pinned, 0);
+        drop(local);
+        assert_eq!(global.stats().registered, 1);
+    }
+    #[test]
+    fn gc_pin_summary() {
+        let global: Global<usize> = Global::default();
+        let locals = global.register_many(4);
+        drop(global.register());
+        let guards = [locals[0].pin(), locals[1].pin()];
+        assert_eq!(global.registered_count(), 4);
+        assert_eq!(global.pin_summary(), [2, 0, 0]);
+
+        assert!(global.try_advance());
+        let late = locals[2].pin();
+        assert_eq!(global.pin_summary(), [2, 1, 0]);
+        // the early pins hold back the next advance
+        assert!(!global.try_advance());
+        drop(guards);
+        assert_eq!(global.pin_summary(), [0, 1, 0]);
+        drop(late);
+        assert_eq!(global.pin_summary(), [0; 3]);
+    }
+    #[test]
+    fn gc_on_migrate() {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut global: Global<usize, 4> = Global::default();
+        let pending = fired.clone();
+        global.on_migrate(10, move |stats| pending.lock().unwrap().push(stats.pending));
+
+        let local = global.register();
+        let guard = local.pin();
+        for i in 0..20 {
+            guard.defer(Box::new(i));
+        }
+        assert_eq!(*fired.lock().unwrap(), [12]);
+        drop(guard);
+        for _ in 0..3 {
+            global.try_advance();
+        }
+        assert_eq!(global.stats().pending, 0);
+
+        let guard = local.pin();
+        for i in 0..12 {
+            guard.defer(Box::new(i));
+        }
+        assert_eq!(*fired.lock().unwrap(), [12, 12]);
+
+        // the migrating thread owning the flags, as during an advance
+        drop(guard);
+        for _ in 0..3 {
+            global.try_advance();
+        }
+        let flags = global.flags.try_own().unwrap();
+        let guard = local.pin();
+        for i in 0..12 {
+            guard.defer(Box::new(i));
+        }
+        drop(flags);
+        assert_eq!(*fired.lock().unwrap(), [12, 12, 12]);
+    }
+    #[test]
+    fn gc_backoff() {
+        let global: Global<usize, 1> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+
+        // losing the race for the flags skips the next attempt
+        let flags = global.flags.try_own().unwrap();
+        guard.defer(Box::new(0));
+        drop(flags);
+        guard.defer(Box::new(1));
+        assert_eq!(global.stats().advances, 0);
+        guard.defer(Box::new(2));
+        assert_eq!(global.stats().advances, 1);
+    }
+    #[test]
+    fn gc_fairness() {
+        let global: Global<usize, 4> = Global::default();
+        let done = AtomicBool::new(false);
+        let max_pending = AtomicUsize::new(0);
+        thread::scope(|s| {
+            // one hot migrator against a few occasional ones
+            s.spawn(|| {
+                let local = global.register();
+                for i in 0..50_000 {
+                    let guard = local.pin();
+                    guard.defer(Box::new(i));
+                    let pending = global.counters.pending.load(Ordering::Relaxed);
+                    max_pending.fetch_max(pending, Ordering::Relaxed);
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+            for _ in 0..7 {
+                s.spawn(|| {
+                    let local = global.register();
+                    while !done.load(Ordering::Relaxed) {
+                        let guard = local.pin();
+                        guard.defer(Box::new(0));
+                        drop(guard);
+                        thread::yield_now();
+                    }
+                });
+            }
+        });
+        // a few epochs worth of bags, at most a starvation limit each
+        assert!(max_pending.load(Ordering::Relaxed) < 3 * (STARVATION_LIMIT + 8) * 4);
+    }
+    #[test]
+    fn gc_steal_bag() {
+        let global: Global<usize, 4> = Global::default();
+        let done = AtomicBool::new(false);
+        let mut stolen = thread::scope(|s| {
+            let helper = s.spawn(|| {
+                let mut stolen = Vec::new();
+                loop {
+                    // read before stealing, so nothing is left behind
+                    let done = done.load(Ordering::Acquire);
+                    match global.steal_bag() {
+                        Some(mut bag) => stolen.extend(bag.drain().map(|garbage| *garbage)),
+                        None if done && global.stats().pending == 0 => break stolen,
+                        // advance only past empty bags, reclaiming one here
+                        // would drop garbage meant to be stolen
+                        None => {
+                            global.collect_with_budget(0);
+                        }
+                    }
+                }
+            });
+            let local = global.register();
+            for i in 0..1000 {
+                let guard = local.pin();
+                guard.defer(Box::new(i));
+            }
+            drop(local);
+            done.store(true, Ordering::Release);
+            helper.join().unwrap()
+        });
+        // the last bags can only have been stolen
+        assert!(stolen.contains(&999));
+        let len = stolen.len();
+        stolen.sort_unstable();
+        stolen.dedup();
+        assert_eq!(stolen.len(), len);
+        assert_eq!(global.pending_garbage(), 0);
+    }
+    #[test]
+    fn gc_steal_bag_blocked() {
+        let global: Global<usize, 1> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        guard.defer(Box::new(0));
+        // the full bag migrated and advanced, the guard may still reach it
+        assert_eq!(global.epoch().value(), 1);
+        assert!(global.steal_bag().is_none());
+        drop(guard);
+        let mut bag = global.steal_bag().unwrap();
+        assert_eq!(bag.drain().map(|garbage| *garbage).collect::<Vec<_>>(), [0]);
+        assert_eq!(global.stats().pending, 0);
+    }
+    #[test]
+    fn gc_collect_with_budget() {
+        let global: Global<usize, 1> = Global::default();
+        let local = global.register();
+
+        // the first migration advances, the others pile up in the next epoch
+        let guard = local.pin();
+        for i in 0..5 {
+            guard.defer(Box::new(i));
+        }
+        drop(guard);
+        assert_eq!(global.stats().advances, 1);
+
+        assert_eq!(global.collect_with_budget(2), 1);
+        assert_eq!(global.stats().advances, 2);
+        assert_eq!(global.collect_with_budget(2), 2);
+        assert_eq!(global.stats().advances, 2);
+        assert_eq!(global.collect_with_budget(2), 2);
+        assert_eq!(global.stats().advances, 3);
+        assert_eq!(global.stats().reclaimed_bags, 5);
+    }
+    #[test]
+    fn gc_custom_reclaim() {
+        #[derive(Default)]
+        struct Collect(Mutex<Vec<usize>>);
+
+        impl Reclaim<usize> for Collect {
+            fn reclaim(&self, garbage: usize) {
+                self.0.lock().unwrap().push(garbage);
+            }
+        }
+
+        let global: Global<usize, 1, 3, Collect> = Global::default();
+        let reclaimed = || global.reclaim.0.lock().unwrap().clone();
+        let local = global.register();
+
+        let guard = local.pin();
+        guard.defer(Box::new(1));
+        guard.defer(Box::new(2));
+        drop(guard);
+        assert!(reclaimed().is_empty());
+        assert!(global.try_advance());
+        assert_eq!(reclaimed(), [1]);
+        assert!(global.try_advance());
+        assert_eq!(reclaimed(), [1, 2]);
+
+        let guard = local.pin();
+        guard.defer(Box::new(3));
+        drop(guard);
+        drop(local);
+        assert_eq!(reclaimed(), [1, 2]);
+    }
+    #[test]
+    fn gc_register_while_collecting() {
+        const IDS: usize = 50_000;
+        /// Marks the ids of the objects reclaimed.
+        struct Mark(Vec<AtomicBool>);
+
+        impl Reclaim<usize> for Mark {
+            fn reclaim(&self, id: usize) {
+                self.0[id].store(true, Ordering::Relaxed);
+            }
+        }
+
+        let global: Global<usize, 4, 3, Mark> =
+            Global::with_reclaim(Mark((0..IDS).map(|_| AtomicBool::new(false)).collect()));
+        let reclaimed = |id: usize| global.reclaim.0[id].load(Ordering::Relaxed);
+        // the id of the live object, replaced ones are retired
+        let slot = AtomicUsize::new(0);
+        let next = AtomicUsize::new(1);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let local = global.register();
+                    loop {
+                        let id = next.fetch_add(1, Ordering::Relaxed);
+                        if id >= IDS {
+                            break;
+                        }
+                        let guard = local.pin();
+                        let old = slot.swap(id, Ordering::AcqRel);
+                        guard.defer(Box::new(old));
+                    }
+                });
+            }
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        // a fresh participant on every read
+                        let local = global.register();
+                        let guard = local.pin();
+                        let id = slot.load(Ordering::Acquire);
+                        for _ in 0..16 {
+                            assert!(!reclaimed(id), "object {id} reclaimed while referenced");
+                            std::hint::spin_loop();
+                        }
+                        drop(guard);
+                    }
+                });
+            }
+        });
+    }
+    #[test]
     fn gc_onfly_register() {
         let global: Global<usize, 1> = Global::default();
 
@@ -174,11 +1948,138 @@ pub mod test {
                     let local = global.register();
                     for i in 0..1000 {
                         let guard = local.pin();
+                        let ran = Arc::new(AtomicBool::new(false));
+                        let flag = ran.clone();
+                        local.defer(&guard, move || flag.store(true, Ordering::Relaxed));
                         local.migrate(&guard, Box::new(i % 3));
+                        global.try_advance();
+                        global.try_advance();
+                        // still pinned in the epoch it was retired in
+                        assert!(!ran.load(Ordering::Relaxed));
                         drop(guard);
                     }
                 });
             }
         });
     }
+    #[test]
+    fn gc_drain_epoch() {
+        let global: Global<DropFlag> = Global::default();
+        let local = global.register();
+        let dropped: Vec<_> = (0..3).map(|_| Arc::new(AtomicBool::new(false))).collect();
+
+        let guard = local.pin();
+        for flag in &dropped {
+            guard.defer(Box::new(DropFlag(flag.clone())));
+        }
+        // filed under the live epoch, which the guard pinned in
+        local.flush(&guard);
+        let epoch = guard.epoch();
+        drop(guard);
+        assert!(dropped.iter().all(|flag| !flag.load(Ordering::Relaxed)));
+
+        // the only participant is unpinned
+        assert_eq!(unsafe { global.drain_epoch(epoch.increase()) }, 0);
+        assert_eq!(unsafe { global.drain_epoch(epoch) }, 1);
+        assert!(dropped.iter().all(|flag| flag.load(Ordering::Relaxed)));
+        assert_eq!(global.pending_garbage(), 0);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom {
+    use core::ptr;
+
+    use loom::{
+        sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+        thread,
+    };
+
+    use super::Global;
+
+    struct Token(&'static AtomicBool);
+
+    impl Drop for Token {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    #[test]
+    fn loom_gc_advance() {
+        loom::model(|| {
+            // leaked so registration can happen before spawning, it is not
+            // under test
+            let global: &'static Global<Token, 1> = Box::leak(Box::default());
+            let freed: &'static _ = Box::leak(Box::new(AtomicBool::new(false)));
+            let shared: &'static _ = Box::leak(Box::new(AtomicPtr::new(Box::into_raw(Box::new(
+                Token(freed),
+            )))));
+            let (reader, writer) = (global.register(), global.register());
+
+            let handle = thread::spawn(move || {
+                let _guard = reader.pin();
+                // reachable garbage is never reclaimed under the guard
+                if !shared.load(Ordering::Acquire).is_null() {
+                    assert!(!freed.load(Ordering::Acquire));
+                }
+            });
+
+            let guard = writer.pin();
+            let old = shared.swap(ptr::null_mut(), Ordering::AcqRel);
+            guard.defer(unsafe { Box::from_raw(old) });
+            drop(guard);
+            global.try_advance();
+            global.try_advance();
+
+            handle.join().unwrap();
+        });
+    }
+    #[test]
+    fn loom_gc_repin() {
+        loom::model(|| {
+            let global: &'static Global<Token, 1> = Box::leak(Box::default());
+            let freed: &'static _ = Box::leak(Box::new(AtomicBool::new(false)));
+            let shared: &'static _ = Box::leak(Box::new(AtomicPtr::new(Box::into_raw(Box::new(
+                Token(freed),
+            )))));
+            let (reader, writer) = (global.register(), global.register());
+
+            let handle = thread::spawn(move || {
+                let guard = reader.pin();
+                // kept as is or pinned again, depending on the advances
+                let _guard = reader.repin(guard);
+                if !shared.load(Ordering::Acquire).is_null() {
+                    assert!(!freed.load(Ordering::Acquire));
+                }
+            });
+
+            let guard = writer.pin();
+            let old = shared.swap(ptr::null_mut(), Ordering::AcqRel);
+            guard.defer(unsafe { Box::from_raw(old) });
+            drop(guard);
+            global.try_advance();
+            global.try_advance();
+
+            handle.join().unwrap();
+        });
+    }
+    #[test]
+    fn loom_gc_pin_validates_epoch() {
+        loom::model(|| {
+            let global: &'static Global<usize, 1> = Box::leak(Box::default());
+            let reader = global.register();
+
+            let handle = thread::spawn(move || {
+                let guard = reader.pin();
+                // pinning never lags far enough to stop blocking reclamation
+                let live = global.epoch.load(Ordering::Relaxed);
+                assert!(guard.epoch.distance(live) <= 1);
+            });
+            global.try_advance();
+            global.try_advance();
+
+            handle.join().unwrap();
+        });
+    }
 }
@@ -0,0 +1,400 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{cell::Cell, fmt, mem, ops::Deref, ptr};
+
+use crate::sync::{
+    self,
+    atomic::{fence, AtomicBool, AtomicPtr, Ordering},
+};
+
+use super::{
+    gc::{DropReclaim, Reclaim},
+    stack::{AtomicStack, StackGuard},
+};
+
+/// Hazard record of one participant, never freed before its domain.
+struct Record<T> {
+    hazard: AtomicPtr<T>,
+    active: AtomicBool,
+}
+
+impl<T> fmt::Debug for Record<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Record")
+            .field("hazard", &self.hazard.load(Ordering::Relaxed))
+            .field("active", &self.active.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Hazard-pointer counterpart of [`Global`](super::gc::Global).
+///
+/// Instead of pinning an epoch, each participant publishes the single pointer
+/// it reads, and retired garbage is reclaimed as soon as no record protects
+/// it. A participant stuck while protecting only delays its own object.
+///
+/// Each [`HazardLocal`] scans the records once `CAP` garbage is retired.
+/// Code generic over [`Domain`](super::domain::Domain) runs on either.
+pub struct HazardGlobal<T, const CAP: usize = 128, R: Reclaim<T> = DropReclaim> {
+    records: AtomicStack<Record<T>>,
+    // garbage left by retired participants, adopted by the next scan
+    orphans: AtomicStack<Box<T>>,
+    reclaim: R,
+}
+
+unsafe impl<T: Send, const CAP: usize, R: Reclaim<T> + Send> Send for HazardGlobal<T, CAP, R> {}
+unsafe impl<T: Send, const CAP: usize, R: Reclaim<T> + Sync> Sync for HazardGlobal<T, CAP, R> {}
+
+/// Prints the number of orphans rather than walking them, a scan may be
+/// popping and freeing them meanwhile.
+impl<T, const CAP: usize, R: Reclaim<T> + fmt::Debug> fmt::Debug for HazardGlobal<T, CAP, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HazardGlobal")
+            .field("records", &self.records.len())
+            .field("orphans", &self.orphans.len())
+            .field("reclaim", &self.reclaim)
+            .finish()
+    }
+}
+
+impl<T, const CAP: usize, R: Reclaim<T> + Default> Default for HazardGlobal<T, CAP, R> {
+    fn default() -> Self {
+        Self::with_reclaim(R::default())
+    }
+}
+
+impl<T, const CAP: usize, R: Reclaim<T>> Drop for HazardGlobal<T, CAP, R> {
+    fn drop(&mut self) {
+        // no participant outlives the domain
+        while let Some(garbage) = unsafe { self.orphans.boxed_pop() } {
+            self.reclaim.reclaim(**garbage);
+        }
+    }
+}
+
+impl<T, const CAP: usize, R: Reclaim<T>> HazardGlobal<T, CAP, R> {
+    pub fn with_reclaim(reclaim: R) -> Self {
+        Self {
+            records: AtomicStack::default(),
+            orphans: AtomicStack::default(),
+            reclaim,
+        }
+    }
+    pub fn register(&self) -> HazardLocal<'_, T, CAP, R> {
+        let record = self
+            .records
+            .try_own()
+            .and_then(|stack_guard| self.reuse_record(&stack_guard))
+            .unwrap_or_else(|| {
+                self.records.push(Record {
+                    hazard: AtomicPtr::new(ptr::null_mut()),
+                    active: AtomicBool::new(true),
+                })
+            });
+        HazardLocal {
+            retired: Cell::new(Vec::with_capacity(CAP)),
+            record,
+            protecting: Cell::new(false),
+            global: self,
+        }
+    }
+    fn reuse_record<'a>(&'a self, stack_guard: &StackGuard<Record<T>>) -> Option<&'a Record<T>> {
+        let record = self.records.iter(stack_guard).find(|record| {
+            record
+                .active
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })?;
+        // records are never freed before the domain
+        Some(unsafe { &*(record as *const Record<T>) })
+    }
+    /// Reclaim the unprotected part of `retired` and of the orphaned garbage,
+    /// keeping the rest in `retired`.
+    ///
+    /// Skipped if another scan is running.
+    fn scan(&self, retired: &mut Vec<Box<T>>) {
+        // holding the records also makes this the only popper of `orphans`
+        let Some(stack_guard) = self.records.try_own() else {
+            return;
+        };
+        // pairs with the fence in `HazardLocal::protect`
+        fence(Ordering::SeqCst);
+        while let Some(garbage) = unsafe { self.orphans.boxed_pop() } {
+            retired.push(*garbage);
+        }
+        let mut hazards: Vec<*mut T> = self
+            .records
+            .iter(&stack_guard)
+            .map(|record| record.hazard.load(Ordering::Acquire))
+            .filter(|hazard| !hazard.is_null())
+            .collect();
+        hazards.sort_unstable();
+        drop(stack_guard);
+
+        for garbage in mem::take(retired) {
+            let raw = &*garbage as *const T as *mut T;
+            if hazards.binary_search(&raw).is_ok() {
+                retired.push(garbage);
+            } else {
+                self.reclaim.reclaim(*garbage);
+            }
+        }
+    }
+}
+
+/// Participant of a [`HazardGlobal`], holding one hazard record.
+pub struct HazardLocal<'a, T, const CAP: usize = 128, R: Reclaim<T> = DropReclaim> {
+    retired: Cell<Vec<Box<T>>>,
+    record: &'a Record<T>,
+    protecting: Cell<bool>,
+    global: &'a HazardGlobal<T, CAP, R>,
+}
+
+impl<'a, T, const CAP: usize, R: Reclaim<T>> HazardLocal<'a, T, CAP, R> {
+    /// Load `src` and protect the loaded pointer from reclamation until the
+    /// guard drops.
+    ///
+    /// # Safety
+    ///
+    /// `src` must only ever hold null or allocations retired through this
+    /// `HazardGlobal`, anything else may be freed under the guard.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another [`HazardGuard`] of this participant is alive, each
+    /// participant owns a single hazard.
+    pub unsafe fn protect(&self, src: &AtomicPtr<T>) -> HazardGuard<'_, T> {
+        assert!(!self.protecting.replace(true), "already protecting");
+        let mut raw = src.load(Ordering::Acquire);
+        loop {
+            self.record.hazard.store(raw, Ordering::SeqCst);
+            // the hazard must be visible before `src` is validated, or a scan
+            // could miss it
+            fence(Ordering::SeqCst);
+            let current = src.load(Ordering::Acquire);
+            if current == raw {
+                break;
+            }
+            raw = current;
+            sync::spin_loop();
+        }
+        HazardGuard {
+            raw,
+            record: self.record,
+            protecting: &self.protecting,
+        }
+    }
+    /// Retire `garbage`, already unlinked from shared memory, to be reclaimed
+    /// once no hazard points to it.
+    pub fn retire(&self, garbage: Box<T>) {
+        let mut retired = self.retired.take();
+        retired.push(garbage);
+        if retired.len() >= CAP {
+            self.global.scan(&mut retired);
+        }
+        self.retired.set(retired);
+    }
+    /// Scan now instead of waiting for `CAP` retired garbage.
+    pub fn collect(&self) {
+        let mut retired = self.retired.take();
+        self.global.scan(&mut retired);
+        self.retired.set(retired);
+    }
+    /// Count of retired garbage not reclaimed yet.
+    pub fn retired_len(&self) -> usize {
+        let retired = self.retired.take();
+        let len = retired.len();
+        self.retired.set(retired);
+        len
+    }
+}
+
+impl<'a, T, const CAP: usize, R: Reclaim<T>> Drop for HazardLocal<'a, T, CAP, R> {
+    fn drop(&mut self) {
+        let retired = self.retired.get_mut();
+        self.global.scan(retired);
+        for garbage in retired.drain(..) {
+            self.global.orphans.push(garbage);
+        }
+        self.record.active.store(false, Ordering::Release);
+    }
+}
+
+impl<'a, T, const CAP: usize, R: Reclaim<T>> fmt::Debug for HazardLocal<'a, T, CAP, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HazardLocal")
+            .field("record", self.record)
+            .field("retired", &self.retired_len())
+            .finish()
+    }
+}
+
+/// Protection of one pointer, see [`HazardLocal::protect`].
+#[derive(Debug)]
+pub struct HazardGuard<'a, T> {
+    raw: *mut T,
+    record: &'a Record<T>,
+    protecting: &'a Cell<bool>,
+}
+
+impl<'a, T> HazardGuard<'a, T> {
+    pub fn as_ptr(&self) -> *mut T {
+        self.raw
+    }
+    /// The protected value, `None` if the loaded pointer was null.
+    pub fn as_ref(&self) -> Option<&T> {
+        unsafe { self.raw.as_ref() }
+    }
+}
+
+impl<'a, T> Deref for HazardGuard<'a, T> {
+    type Target = T;
+
+    /// # Panics
+    ///
+    /// Panics if the protected pointer is null.
+    fn deref(&self) -> &T {
+        self.as_ref().expect("null pointer protected")
+    }
+}
+
+impl<'a, T> Drop for HazardGuard<'a, T> {
+    fn drop(&mut self) {
+        self.record.hazard.store(ptr::null_mut(), Ordering::Release);
+        self.protecting.set(false);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicPtr, AtomicUsize, Ordering},
+            mpsc,
+        },
+        thread,
+    };
+
+    use super::HazardGlobal;
+
+    struct Counted<'a>(&'a AtomicUsize);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn hazard_protect() {
+        let dropped = AtomicUsize::new(0);
+        let global: HazardGlobal<Counted, 4> = HazardGlobal::default();
+        let local = global.register();
+        let src = AtomicPtr::new(Box::into_raw(Box::new(Counted(&dropped))));
+
+        let guard = unsafe { local.protect(&src) };
+        let old = src.swap(std::ptr::null_mut(), Ordering::AcqRel);
+        assert_eq!(old, guard.as_ptr());
+        local.retire(unsafe { Box::from_raw(old) });
+        local.collect();
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+        assert_eq!(local.retired_len(), 1);
+
+        drop(guard);
+        local.collect();
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(local.retired_len(), 0);
+    }
+    #[test]
+    #[should_panic(expected = "already protecting")]
+    fn hazard_protect_twice() {
+        let global: HazardGlobal<usize> = HazardGlobal::default();
+        let local = global.register();
+        let src = AtomicPtr::new(std::ptr::null_mut());
+        let _guard = unsafe { local.protect(&src) };
+        let _guard = unsafe { local.protect(&src) };
+    }
+    #[test]
+    fn hazard_stuck_participant() {
+        let dropped = AtomicUsize::new(0);
+        let global: HazardGlobal<Counted, 8> = HazardGlobal::default();
+        let src = AtomicPtr::new(Box::into_raw(Box::new(Counted(&dropped))));
+        let (protected, stuck) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+
+        thread::scope(|s| {
+            let (global, src) = (&global, &src);
+            let stuck_thread = s.spawn(move || {
+                let local = global.register();
+                let guard = unsafe { local.protect(src) };
+                protected.send(guard.as_ptr() as usize).unwrap();
+                // never unprotects while others retire
+                released.recv().unwrap();
+            });
+            let stuck_ptr = stuck.recv().unwrap() as *mut Counted;
+
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let local = global.register();
+                        for _ in 0..100 {
+                            local.retire(Box::new(Counted(&dropped)));
+                        }
+                        local.collect();
+                    })
+                })
+                .collect();
+            for worker in workers {
+                worker.join().unwrap();
+            }
+            // adopt what workers left behind when their scans overlapped
+            let local = global.register();
+            local.collect();
+            // all garbage of the workers is reclaimed despite the stuck one
+            assert_eq!(dropped.load(Ordering::Relaxed), 400);
+
+            let old = src.swap(std::ptr::null_mut(), Ordering::AcqRel);
+            assert_eq!(old, stuck_ptr);
+            local.retire(unsafe { Box::from_raw(old) });
+            local.collect();
+            assert_eq!(dropped.load(Ordering::Relaxed), 400);
+
+            release.send(()).unwrap();
+            stuck_thread.join().unwrap();
+            local.collect();
+            assert_eq!(dropped.load(Ordering::Relaxed), 401);
+        });
+    }
+    #[test]
+    fn hazard_reuse_record() {
+        let global: HazardGlobal<usize> = HazardGlobal::default();
+        drop(global.register());
+        drop(global.register());
+        let _a = global.register();
+        let _b = global.register();
+        let stack_guard = global.records.try_own().unwrap();
+        assert_eq!(global.records.iter(&stack_guard).count(), 2);
+    }
+    #[test]
+    fn hazard_orphans() {
+        let dropped = AtomicUsize::new(0);
+        let global: HazardGlobal<Counted> = HazardGlobal::default();
+        let src = AtomicPtr::new(Box::into_raw(Box::new(Counted(&dropped))));
+        let other = global.register();
+        let guard = unsafe { other.protect(&src) };
+
+        let local = global.register();
+        local.retire(unsafe { Box::from_raw(src.swap(std::ptr::null_mut(), Ordering::AcqRel)) });
+        // still protected, left to the domain
+        drop(local);
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+
+        assert_eq!(
+            format!("{global:?}"),
+            "HazardGlobal { records: 2, orphans: 1, reclaim: DropReclaim }"
+        );
+
+        drop(guard);
+        other.collect();
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+    }
+}
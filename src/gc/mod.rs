@@ -1,8 +1,23 @@
+#[cfg(feature = "std")]
+pub mod collector;
+pub mod domain;
 pub mod epoch;
+#[allow(clippy::module_inception)]
 pub mod gc;
+pub mod hazard;
+pub mod pointer;
+#[cfg(feature = "std")]
+pub mod reaper;
 pub mod stack;
 
 pub mod prelude {
     use super::*;
+    #[cfg(feature = "std")]
+    pub use collector::{Collector, OwnedPinGuard};
+    pub use domain::{Domain, Participant, Protected};
     pub use gc::*;
+    pub use hazard::{HazardGlobal, HazardGuard, HazardLocal};
+    pub use pointer::{Atomic, CompareExchangeError, Owned, Shared};
+    #[cfg(feature = "std")]
+    pub use reaper::Reaper;
 }
@@ -0,0 +1,310 @@
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr,
+};
+
+use super::gc::{PinGuard, Reclaim};
+use crate::sync::atomic::{AtomicPtr, Ordering};
+
+/// Atomic pointer to a heap allocated `T`, read under a [`PinGuard`].
+///
+/// Replaced values are not freed, they are returned as [`Shared`] so the
+/// caller can retire them through [`PinGuard::defer`].
+/// The value held when the `Atomic` drops is freed with it.
+///
+/// Stores publish with `Release` and loads read with `Acquire`, so a loaded
+/// pointee is always fully initialized.
+pub struct Atomic<T> {
+    ptr: AtomicPtr<T>,
+    _marker: PhantomData<Box<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for Atomic<T> {}
+unsafe impl<T: Send + Sync> Sync for Atomic<T> {}
+
+impl<T> Default for Atomic<T> {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl<T> Drop for Atomic<T> {
+    fn drop(&mut self) {
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        if !ptr.is_null() {
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
+
+impl<T> fmt::Debug for Atomic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Atomic")
+            .field(&self.ptr.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Returned by a failed [`Atomic::compare_exchange`].
+#[derive(Debug)]
+pub struct CompareExchangeError<'g, T> {
+    /// Value observed instead of the expected one.
+    pub current: Shared<'g, T>,
+    /// The value that was not installed.
+    pub new: Owned<T>,
+}
+
+impl<T> Atomic<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            _marker: PhantomData,
+        }
+    }
+    pub fn null() -> Self {
+        Self {
+            ptr: AtomicPtr::default(),
+            _marker: PhantomData,
+        }
+    }
+    pub fn load<'g, G, const CAP: usize, const N: usize, R: Reclaim<G>>(
+        &'g self,
+        _guard: &'g PinGuard<'_, G, CAP, N, R>,
+    ) -> Shared<'g, T> {
+        Shared::from_raw(self.ptr.load(Ordering::Acquire))
+    }
+    /// Install `new` without retiring the previous value, which leaks unless
+    /// it is retired by whoever else observed it.
+    pub fn store(&self, new: Owned<T>) {
+        self.ptr.store(new.into_raw(), Ordering::Release);
+    }
+    /// Install `new`, returning the previous value for the caller to retire.
+    pub fn swap<'g, G, const CAP: usize, const N: usize, R: Reclaim<G>>(
+        &'g self,
+        new: Owned<T>,
+        _guard: &'g PinGuard<'_, G, CAP, N, R>,
+    ) -> Shared<'g, T> {
+        Shared::from_raw(self.ptr.swap(new.into_raw(), Ordering::AcqRel))
+    }
+    /// Install `new` if the current value is `current`.
+    ///
+    /// On success the replaced value is returned for the caller to retire.
+    pub fn compare_exchange<'g, G, const CAP: usize, const N: usize, R: Reclaim<G>>(
+        &'g self,
+        current: Shared<'_, T>,
+        new: Owned<T>,
+        _guard: &'g PinGuard<'_, G, CAP, N, R>,
+    ) -> Result<Shared<'g, T>, CompareExchangeError<'g, T>> {
+        let new = new.into_raw();
+        self.ptr
+            .compare_exchange(
+                current.ptr as *mut T,
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .map(Shared::from_raw)
+            .map_err(|actual| CompareExchangeError {
+                current: Shared::from_raw(actual),
+                new: Owned(unsafe { Box::from_raw(new) }),
+            })
+    }
+}
+
+impl<T> From<Owned<T>> for Atomic<T> {
+    fn from(owned: Owned<T>) -> Self {
+        Self {
+            ptr: AtomicPtr::new(owned.into_raw()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Uniquely owned allocation that has not been published to an [`Atomic`]
+/// yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Owned<T>(Box<T>);
+
+impl<T> Owned<T> {
+    pub fn new(value: T) -> Self {
+        Self(Box::new(value))
+    }
+    pub fn into_box(self) -> Box<T> {
+        self.0
+    }
+    /// Give up ownership for publishing.
+    fn into_raw(self) -> *mut T {
+        Box::into_raw(self.0)
+    }
+}
+
+impl<T> From<Box<T>> for Owned<T> {
+    fn from(value: Box<T>) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Owned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Pointer loaded from an [`Atomic`], valid as long as the [`PinGuard`] it
+/// was loaded under.
+///
+/// It cannot outlive the guard:
+///
+/// ```compile_fail
+/// use atomic::gc::prelude::*;
+///
+/// let global: Global<usize> = Global::default();
+/// let local = global.register();
+/// let atomic = Atomic::new(0);
+/// let guard = local.pin();
+/// let shared = atomic.load(&guard);
+/// drop(guard);
+/// shared.as_ref();
+/// ```
+pub struct Shared<'g, T> {
+    ptr: *const T,
+    _marker: PhantomData<&'g T>,
+}
+
+impl<'g, T> Clone for Shared<'g, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'g, T> Copy for Shared<'g, T> {}
+
+impl<'g, T> PartialEq for Shared<'g, T> {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.ptr, other.ptr)
+    }
+}
+
+impl<'g, T> fmt::Debug for Shared<'g, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Shared").field(&self.ptr).finish()
+    }
+}
+
+impl<'g, T> Shared<'g, T> {
+    fn from_raw(ptr: *mut T) -> Self {
+        Self {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+    pub fn null() -> Self {
+        Self::from_raw(ptr::null_mut())
+    }
+    pub fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+    pub fn as_raw(&self) -> *const T {
+        self.ptr
+    }
+    /// The pointee, it cannot be reclaimed while the guard is alive.
+    pub fn as_ref(&self) -> Option<&'g T> {
+        unsafe { self.ptr.as_ref() }
+    }
+    /// Take ownership of the pointee, typically to retire it.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be non-null and unlinked from its [`Atomic`], and no
+    /// one else may take ownership of it. Unless no other thread can still
+    /// load it, it must only be freed by retiring it through the same
+    /// [`Global`](super::gc::Global) every reader of the `Atomic` pins.
+    pub unsafe fn into_box(self) -> Box<T> {
+        debug_assert!(!self.ptr.is_null());
+        Box::from_raw(self.ptr as *mut T)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::{Atomic, Owned};
+    use crate::gc::gc::Global;
+
+    struct Counted(usize, Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn atomic_swap() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let global: Global<Counted, 1> = Global::default();
+        let local = global.register();
+        let atomic = Atomic::new(Counted(0, dropped.clone()));
+
+        for i in 1..4 {
+            let guard = local.pin();
+            let old = atomic.swap(Owned::new(Counted(i, dropped.clone())), &guard);
+            assert_eq!(old.as_ref().unwrap().0, i - 1);
+            assert_eq!(atomic.load(&guard).as_ref().unwrap().0, i);
+            guard.defer(unsafe { old.into_box() });
+        }
+        // each retiree is reclaimed by the next migration's advance, except
+        // the latest one which is still within its grace period
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+        assert!(global.try_advance());
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+        drop(atomic);
+        assert_eq!(dropped.load(Ordering::Relaxed), 4);
+    }
+    #[test]
+    fn atomic_owned() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let mut owned = Owned::new(1_usize);
+        *owned += 1;
+        let atomic = Atomic::from(owned);
+        let guard = local.pin();
+        assert_eq!(atomic.load(&guard).as_ref(), Some(&2));
+        assert!(Atomic::<usize>::null().load(&guard).is_null());
+    }
+    #[test]
+    fn atomic_compare_exchange() {
+        let global: Global<usize> = Global::default();
+        let local = global.register();
+        let guard = local.pin();
+        let atomic = Atomic::new(1_usize);
+
+        let current = atomic.load(&guard);
+        let old = atomic
+            .compare_exchange(current, Owned::new(2), &guard)
+            .unwrap();
+        assert_eq!(old, current);
+        guard.defer(unsafe { old.into_box() });
+
+        let err = atomic
+            .compare_exchange(current, Owned::new(3), &guard)
+            .unwrap_err();
+        assert_eq!(err.current.as_ref(), Some(&2));
+        assert_eq!(*err.new, 3);
+    }
+}
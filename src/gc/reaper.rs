@@ -0,0 +1,178 @@
+use std::{
+    fmt,
+    thread::{self, JoinHandle},
+};
+
+use super::gc::{Global, GlobalBuilder, Reclaim};
+use crate::channel::{channel, Receiver, Sender};
+
+/// [`Reclaim`] handing garbage to a background thread that drops it, so
+/// expensive destructors don't run on the thread advancing the epoch.
+///
+/// Dropping the reaper waits for the thread to drop everything sent so far.
+pub struct Reaper<T> {
+    sender: Option<Sender<T>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T> fmt::Debug for Reaper<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reaper")
+            .field("thread", &self.thread.as_ref().map(|t| t.thread().id()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> Reaper<T> {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender: Some(sender),
+            thread: Some(thread::spawn(move || reap(receiver))),
+        }
+    }
+}
+
+impl<T: Send + 'static> Default for Reaper<T> {
+    fn default() -> Self {
+        Self::spawn()
+    }
+}
+
+fn reap<T>(mut receiver: Receiver<T>) {
+    loop {
+        // checked before draining, so nothing sent before the disconnect is
+        // left behind
+        let disconnected = receiver.is_disconnected();
+        while let Some(garbage) = receiver.try_recv() {
+            drop(garbage);
+        }
+        if disconnected {
+            break;
+        }
+        // woken by every send and by the disconnect
+        thread::park();
+    }
+}
+
+impl<T> Reclaim<T> for Reaper<T> {
+    fn reclaim(&self, garbage: T) {
+        let (Some(sender), Some(thread)) = (&self.sender, &self.thread) else {
+            unreachable!("only taken on drop");
+        };
+        sender.send(garbage);
+        thread.thread().unpark();
+    }
+}
+
+impl<T> Drop for Reaper<T> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            // a panicking destructor already reported itself
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<T: Send + 'static, const CAP: usize, const N: usize> Global<T, CAP, N, Reaper<T>> {
+    /// Global dropping its garbage on a background thread, see [`Reaper`].
+    ///
+    /// Dropping the `Global` joins the thread once the pending garbage is
+    /// dropped.
+    pub fn with_reaper() -> Self {
+        Self::with_reclaim(Reaper::spawn())
+    }
+}
+
+impl<T: Send + 'static, const CAP: usize, const N: usize, R> GlobalBuilder<T, CAP, N, R> {
+    /// Drop the garbage on a background thread, see [`Global::with_reaper`].
+    pub fn with_reaper(self) -> GlobalBuilder<T, CAP, N, Reaper<T>> {
+        self.reclaim(Reaper::spawn())
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::{
+        sync::{Arc, Condvar, Mutex},
+        thread::{self, ThreadId},
+        time::Duration,
+    };
+
+    use super::Reaper;
+    use crate::gc::gc::Global;
+
+    const DELAY: Duration = Duration::from_millis(2);
+
+    /// Records the threads it dropped on.
+    struct Slow(Arc<Mutex<Vec<ThreadId>>>);
+
+    impl Drop for Slow {
+        fn drop(&mut self) {
+            thread::sleep(DELAY);
+            self.0.lock().unwrap().push(thread::current().id());
+        }
+    }
+
+    /// Blocks its drop until the gate opens, unless dropped on the thread that
+    /// retired it, and records the thread it dropped on.
+    struct Gated {
+        gate: Arc<(Mutex<bool>, Condvar)>,
+        retired_on: ThreadId,
+        dropped: Arc<Mutex<Vec<ThreadId>>>,
+    }
+
+    impl Drop for Gated {
+        fn drop(&mut self) {
+            let id = thread::current().id();
+            if id != self.retired_on {
+                let (open, opened) = &*self.gate;
+                drop(opened.wait_while(open.lock().unwrap(), |open| !*open));
+            }
+            self.dropped.lock().unwrap().push(id);
+        }
+    }
+
+    #[test]
+    fn reaper_builder() {
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let global = Global::builder().cap::<1>().with_reaper().build();
+        global
+            .register()
+            .pin_and_defer(Box::new(Slow(dropped.clone())));
+        drop(global);
+        let dropped = dropped.lock().unwrap();
+        assert_eq!(dropped.len(), 1);
+        assert_ne!(dropped[0], thread::current().id());
+    }
+    #[test]
+    fn reaper_drops_off_thread() {
+        let gate = Arc::new((Mutex::new(false), Condvar::new()));
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let global: Global<Gated, 4, 3, Reaper<Gated>> = Global::with_reaper();
+        let local = global.register();
+
+        for _ in 0..100 {
+            let guard = local.pin();
+            guard.defer(Box::new(Gated {
+                gate: gate.clone(),
+                retired_on: thread::current().id(),
+                dropped: dropped.clone(),
+            }));
+        }
+        // retiring returned while the reaper is stuck in a destructor
+        assert!(global.stats().reclaimed_bags > 0);
+        assert!(dropped.lock().unwrap().is_empty());
+
+        *gate.0.lock().unwrap() = true;
+        gate.1.notify_all();
+        drop(local);
+        drop(global);
+
+        let dropped = dropped.lock().unwrap();
+        assert_eq!(dropped.len(), 100);
+        assert!(dropped.iter().all(|&id| id != thread::current().id()));
+    }
+}
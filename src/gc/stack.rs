@@ -1,36 +1,346 @@
-use std::{
-    mem,
-    ops::Deref,
-    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+use alloc::{alloc::alloc, boxed::Box, vec, vec::Vec};
+use core::{alloc::Layout, fmt, ops::Deref, ptr};
+
+use crate::sync::{
+    self,
+    atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
 };
 
+/// Link of an [`AtomicStack`], see [`AtomicStack::push_raw`].
 #[derive(Debug)]
-struct Node<T> {
+pub struct Node<T> {
     next: AtomicPtr<Node<T>>,
     data: *mut T,
 }
 
+impl<T> Node<T> {
+    /// The element, the node must still be linked.
+    pub fn value(&self) -> &T {
+        unsafe { &*self.data }
+    }
+}
+
+/// Head pointer of [`AtomicStack`].
+///
+/// On 64-bit targets the unused upper bits carry a tag bumped on every update,
+/// so a CAS against an address that was freed and reused in between (ABA)
+/// fails. Other targets store the bare pointer.
+#[derive(Debug)]
+struct Head<T>(AtomicPtr<Node<T>>);
+
+#[cfg(target_pointer_width = "64")]
+const TAG_SHIFT: u32 = 48;
+
+impl<T> Default for Head<T> {
+    fn default() -> Self {
+        Self(AtomicPtr::default())
+    }
+}
+
+impl<T> Head<T> {
+    /// Strip the tag from a value returned by [`Head::load`].
+    #[inline]
+    fn untag(tagged: *mut Node<T>) -> *mut Node<T> {
+        #[cfg(target_pointer_width = "64")]
+        return tagged.map_addr(|addr| addr & ((1 << TAG_SHIFT) - 1));
+        #[cfg(not(target_pointer_width = "64"))]
+        return tagged;
+    }
+    /// Tag `new` with the tag following `current`'s.
+    #[inline]
+    fn retag(current: *mut Node<T>, new: *mut Node<T>) -> *mut Node<T> {
+        #[cfg(target_pointer_width = "64")]
+        {
+            debug_assert_eq!(new.addr() >> TAG_SHIFT, 0, "pointer overlaps the tag");
+            let tag = (current.addr() >> TAG_SHIFT).wrapping_add(1);
+            new.map_addr(|addr| addr | (tag << TAG_SHIFT))
+        }
+        #[cfg(not(target_pointer_width = "64"))]
+        {
+            let _ = current;
+            new
+        }
+    }
+    /// Tagged head, only meant to be fed back into a compare exchange.
+    #[inline]
+    fn load(&self, ordering: Ordering) -> *mut Node<T> {
+        self.0.load(ordering)
+    }
+    #[inline]
+    fn load_ptr(&self, ordering: Ordering) -> *mut Node<T> {
+        Self::untag(self.load(ordering))
+    }
+    #[inline]
+    fn compare_exchange_weak(
+        &self,
+        current: *mut Node<T>,
+        new: *mut Node<T>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<*mut Node<T>, *mut Node<T>> {
+        self.0
+            .compare_exchange_weak(current, Self::retag(current, new), success, failure)
+    }
+    #[inline]
+    fn compare_exchange(
+        &self,
+        current: *mut Node<T>,
+        new: *mut Node<T>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<*mut Node<T>, *mut Node<T>> {
+        self.0
+            .compare_exchange(current, Self::retag(current, new), success, failure)
+    }
+    /// Replace the head, returning the old untagged pointer.
+    #[inline]
+    fn swap(&self, new: *mut Node<T>, ordering: Ordering) -> *mut Node<T> {
+        let mut current = self.load(Ordering::Relaxed);
+        loop {
+            match self.compare_exchange_weak(current, new, ordering, Ordering::Relaxed) {
+                Ok(old) => return Self::untag(old),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// Free-list of nodes recycled by a pooled [`AtomicStack`].
+///
+/// Any thread gives nodes back, but only one takes at a time, the others
+/// allocate instead. A taker thus only reads nodes that stay in the list
+/// until its CAS, so a node may be freed as soon as it is taken, and the
+/// free list can't suffer ABA even without a tagged head.
+#[derive(Debug)]
+struct Pool<T> {
+    head: Head<T>,
+    len: AtomicUsize,
+    /// Held by the single taker.
+    taking: AtomicBool,
+    capacity: usize,
+}
+
+impl<T> Drop for Pool<T> {
+    fn drop(&mut self) {
+        // `&mut self` makes this the only taker
+        while let Some(node) = unsafe { self.take_owned() } {
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+}
+
+impl<T> Pool<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            head: Default::default(),
+            len: Default::default(),
+            taking: AtomicBool::new(false),
+            capacity,
+        }
+    }
+    /// Take a node, `None` if the pool is empty or another thread is taking.
+    fn take(&self) -> Option<*mut Node<T>> {
+        self.taking
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        // `taking` makes this the only taker
+        let node = unsafe { self.take_owned() };
+        self.taking.store(false, Ordering::Release);
+        node
+    }
+    /// # Safety
+    ///
+    /// The caller must hold `taking`.
+    unsafe fn take_owned(&self) -> Option<*mut Node<T>> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let node = Head::untag(head);
+            if node.is_null() {
+                return None;
+            }
+            // givers only ever replace the head, `node` stays in the list
+            // until taken here
+            let next = unsafe { &*node }.next.load(Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange_weak(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                return Some(node);
+            }
+        }
+    }
+    fn shrink_to(&self, max_pooled: usize) {
+        let mut backoff = sync::Backoff::default();
+        while self
+            .taking
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            backoff.snooze();
+        }
+        while self.len.load(Ordering::Relaxed) > max_pooled {
+            // `taking` is held, nobody else reads the taken node
+            match unsafe { self.take_owned() } {
+                Some(node) => drop(unsafe { Box::from_raw(node) }),
+                None => break,
+            }
+        }
+        self.taking.store(false, Ordering::Release);
+    }
+    /// Hand `node` back to the pool, or return it if the pool is full.
+    fn give(&self, node: *mut Node<T>) -> Result<(), *mut Node<T>> {
+        if self.len.fetch_add(1, Ordering::Relaxed) >= self.capacity {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            return Err(node);
+        }
+        loop {
+            let head = self.head.load(Ordering::Relaxed);
+            unsafe { &*node }
+                .next
+                .store(Head::untag(head), Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange_weak(head, node, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// `Box::new` handing `value` back when the allocator fails instead of
+/// aborting.
+fn try_box<T>(value: T) -> Result<Box<T>, T> {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        // no allocation involved
+        return Ok(Box::new(value));
+    }
+    let raw = unsafe { alloc(layout) }.cast::<T>();
+    if raw.is_null() {
+        return Err(value);
+    }
+    unsafe {
+        raw.write(value);
+        Ok(Box::from_raw(raw))
+    }
+}
+
+/// Another thread changed the head during a single-shot attempt, see
+/// [`AtomicStack::try_pop`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Contended;
+
+/// Exclusive popping rights over an [`AtomicStack`], see
+/// [`AtomicStack::try_own`].
+///
+/// The rights are not tied to a thread, so the guard is `Send` whenever the
+/// stack is `Sync`.
 pub struct StackGuard<'a, T>(&'a AtomicStack<T>);
 
 impl<'a, T> Deref for StackGuard<'a, T> {
     type Target = AtomicStack<T>;
 
     fn deref(&self) -> &Self::Target {
-        unsafe { mem::transmute(self.0) }
+        self.0
     }
 }
 
 impl<'a, T> Drop for StackGuard<'a, T> {
     fn drop(&mut self) {
-        self.0.is_taken.store(false, Ordering::Relaxed);
+        // orders this guard's reads of the nodes before the next owner frees
+        // them, pairs with the `Acquire` of `try_own`
+        self.0.is_taken.store(false, Ordering::Release);
+    }
+}
+
+/// Element pushed by [`AtomicStack::push_returning_guard`], kept from being
+/// popped by the popping rights held alongside.
+pub struct PushGuard<'a, T> {
+    guard: StackGuard<'a, T>,
+    value: &'a T,
+}
+
+impl<'a, T> PushGuard<'a, T> {
+    /// The rights keeping the element, e.g. to iterate the stack meanwhile.
+    pub fn stack_guard(&self) -> &StackGuard<'a, T> {
+        &self.guard
+    }
+}
+
+impl<'a, T> Deref for PushGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for PushGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PushGuard").field(self.value).finish()
     }
 }
 
+/// Lock-free Treiber stack.
+///
+/// Zero-sized `T` is supported: each element still takes a node allocation
+/// for linking, but its data pointer is the dangling one produced by `Box`
+/// and is never read through.
 #[repr(C)]
-#[derive(Debug)]
 pub struct AtomicStack<T> {
-    head: AtomicPtr<Node<T>>,
+    head: Head<T>,
     is_taken: AtomicBool,
+    len: AtomicUsize,
+    pool: Option<Pool<T>>,
+}
+
+// elements are moved in and out by whichever thread owns the stack
+unsafe impl<T: Send> Send for AtomicStack<T> {}
+// pushing and popping through `&self` moves elements across threads, while
+// `peek` and `iter` share them
+unsafe impl<T: Send + Sync> Sync for AtomicStack<T> {}
+
+/// Lists the elements top to bottom, or only the length if another
+/// [`StackGuard`] is alive.
+impl<T: fmt::Debug> fmt::Debug for AtomicStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_own() {
+            Some(guard) => {
+                f.write_str("AtomicStack ")?;
+                f.debug_list().entries(self.iter(&guard)).finish()
+            }
+            None => f
+                .debug_struct("AtomicStack")
+                .field("len", &self.len())
+                .field("elements", &format_args!("<locked>"))
+                .finish(),
+        }
+    }
+}
+
+/// Serialized head-to-tail as a sequence, another live [`StackGuard`] makes
+/// it fail.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for AtomicStack<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let guard = self
+            .try_own()
+            .ok_or_else(|| serde::ser::Error::custom("AtomicStack is owned elsewhere"))?;
+        serializer.collect_seq(self.iter(&guard))
+    }
+}
+
+/// Reproduces the serialized order, the first element ends up on top.
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for AtomicStack<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let elements = Vec::<T>::deserialize(deserializer)?;
+        Ok(elements.into_iter().rev().collect())
+    }
 }
 
 impl<T> Drop for AtomicStack<T> {
@@ -44,76 +354,780 @@ impl<T> Default for AtomicStack<T> {
         Self {
             head: Default::default(),
             is_taken: Default::default(),
+            len: Default::default(),
+            pool: None,
         }
     }
 }
 
+/// Clones every element into a new stack of the same order.
+///
+/// Spins while another [`StackGuard`] of the stack is alive.
+impl<T: Clone> Clone for AtomicStack<T> {
+    fn clone(&self) -> Self {
+        let guard = self.own();
+        let elements: Vec<T> = self.iter(&guard).cloned().collect();
+        let stack = match &self.pool {
+            Some(pool) => Self::with_pool(pool.capacity),
+            None => Self::default(),
+        };
+        (&stack).extend(elements.into_iter().rev());
+        stack
+    }
+}
+
 impl<T> AtomicStack<T> {
-    pub fn push<'a>(&'a self, value: T) -> &'a T {
+    /// Stack recycling up to `capacity` popped nodes for later pushes instead
+    /// of returning them to the allocator.
+    pub fn with_pool(capacity: usize) -> Self {
+        Self {
+            head: Default::default(),
+            is_taken: Default::default(),
+            len: Default::default(),
+            pool: Some(Pool::new(capacity)),
+        }
+    }
+    /// Free pooled nodes until at most `max_pooled` are left, while pushes
+    /// and pops go on.
+    ///
+    /// Waits for a push taking a pooled node, which meanwhile allocates
+    /// instead.
+    pub fn shrink_to(&self, max_pooled: usize) {
+        if let Some(pool) = &self.pool {
+            pool.shrink_to(max_pooled);
+        }
+    }
+    /// Fill the pool with up to `additional` fresh nodes, stopping once it is
+    /// full. No-op for a stack without a pool.
+    pub fn reserve(&self, additional: usize) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        for _ in 0..additional {
+            let node = Box::into_raw(Box::new(Node {
+                next: AtomicPtr::default(),
+                data: ptr::null_mut(),
+            }));
+            if let Err(node) = pool.give(node) {
+                drop(unsafe { Box::from_raw(node) });
+                break;
+            }
+        }
+    }
+    fn alloc_node(&self, data: *mut T) -> *mut Node<T> {
+        match self.pool.as_ref().and_then(Pool::take) {
+            Some(node) => {
+                unsafe { (*node).data = data };
+                node
+            }
+            None => Box::into_raw(Box::new(Node {
+                next: AtomicPtr::default(),
+                data,
+            })),
+        }
+    }
+    /// # Safety
+    ///
+    /// `node` must be unlinked and exclusively owned by the caller.
+    unsafe fn free_node(&self, node: *mut Node<T>) {
+        unsafe { (*node).data = ptr::null_mut() };
+        let node = match &self.pool {
+            Some(pool) => match pool.give(node) {
+                Ok(()) => return,
+                Err(node) => node,
+            },
+            None => node,
+        };
+        drop(unsafe { Box::from_raw(node) });
+    }
+    pub fn push(&self, value: T) -> &T {
         self.boxed_push(Box::new(value))
     }
-    pub fn boxed_push<'a>(&'a self, value: Box<T>) -> &'a T {
-        #[cfg(debug_assertions)]
-        debug_assert_eq!(self.is_taken.load(Ordering::Acquire),false,"expect stack untaken when pushing");
-        let value = Box::into_raw(value);
-        let boxed_node = Box::new(Node {
-            next: AtomicPtr::default(),
-            data: value,
-        });
-        let node = Box::leak(boxed_node);
-
+    /// Like [`AtomicStack::push`], but keep the element alive until the
+    /// returned guard drops: it holds the popping rights, which are taken
+    /// before pushing, waiting for any other guard to drop.
+    ///
+    /// The reference returned by [`AtomicStack::push`] is only valid until
+    /// another thread pops the element.
+    pub fn push_returning_guard(&self, value: T) -> PushGuard<'_, T> {
+        let guard = self.own();
+        let value = self.push(value);
+        PushGuard { guard, value }
+    }
+    pub fn boxed_push(&self, value: Box<T>) -> &T {
+        // `AcqRel` publishes the element to any reader
+        unsafe { self.push_ordered(value, Ordering::AcqRel, Ordering::Relaxed) }
+    }
+    /// Like [`AtomicStack::boxed_push`], with the orderings of the CAS
+    /// linking the node given explicitly, as in
+    /// [`AtomicPtr::compare_exchange`].
+    ///
+    /// `failure` merely reloads the head for a retry, `Relaxed` is enough.
+    ///
+    /// # Safety
+    ///
+    /// Unless `success` is `Release` or stronger, every read of the element
+    /// on another thread, whether by popping, peeking or iterating, must be
+    /// otherwise ordered after the push.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `failure` is `Release` or `AcqRel`.
+    pub unsafe fn push_ordered(&self, value: Box<T>, success: Ordering, failure: Ordering) -> &T {
+        let data = Box::into_raw(value);
+        let node = self.alloc_node(data);
+        // a concurrent pop may recycle the node as soon as it is linked
+        self.link_ordered(node, success, failure);
+        unsafe { &*data }
+    }
+    /// Like [`AtomicStack::boxed_push`], but return the node itself for
+    /// intrusive use.
+    ///
+    /// The pointer is only valid until the node is popped, after which it
+    /// is freed or recycled by the pool.
+    pub fn push_raw(&self, value: Box<T>) -> *mut Node<T> {
+        let node = self.alloc_node(Box::into_raw(value));
+        self.link(node);
+        node
+    }
+    /// The top node as last observed, for [`AtomicStack::cas_head`].
+    ///
+    /// Only meant to be compared by address, dereferencing it is only sound
+    /// while a [`StackGuard`] keeps the nodes alive.
+    pub fn head_ptr(&self) -> *mut Node<T> {
+        self.head.load_ptr(Ordering::Acquire)
+    }
+    /// Push `value` only if the head is still `expected`, typically taken
+    /// from [`AtomicStack::head_ptr`] before computing `value`. Otherwise give
+    /// `value` back along with the current head, to retry from.
+    ///
+    /// Heads are compared by address only: if the observed node was popped
+    /// and its memory reused for a new head in between, the push succeeds.
+    pub fn cas_head(&self, expected: *mut Node<T>, value: T) -> Result<&T, (T, *mut Node<T>)> {
+        let head = self.head.load(Ordering::Acquire);
+        if Head::untag(head) != expected {
+            return Err((value, Head::untag(head)));
+        }
+        let data = Box::into_raw(Box::new(value));
+        let node = self.alloc_node(data);
+        unsafe { &*node }.next.store(expected, Ordering::Relaxed);
+        self.len.fetch_add(1, Ordering::Relaxed);
+        match self
+            .head
+            .compare_exchange(head, node, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => Ok(unsafe { &*data }),
+            Err(actual) => {
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                // never linked
+                unsafe { self.free_node(node) };
+                Err((*unsafe { Box::from_raw(data) }, Head::untag(actual)))
+            }
+        }
+    }
+    /// Like [`AtomicStack::push`], but give `value` back instead of aborting
+    /// if allocating its box or node fails.
+    pub fn try_push(&self, value: T) -> Result<&T, T> {
+        let value = try_box(value)?;
+        let node = match self.pool.as_ref().and_then(Pool::take) {
+            Some(node) => node,
+            None => match try_box(Node {
+                next: AtomicPtr::default(),
+                data: ptr::null_mut(),
+            }) {
+                Ok(node) => Box::into_raw(node),
+                Err(_) => return Err(*value),
+            },
+        };
+        let data = Box::into_raw(value);
+        unsafe { (*node).data = data };
+        self.link(node);
+        Ok(unsafe { &*data })
+    }
+    fn link(&self, node: *mut Node<T>) {
+        self.link_ordered(node, Ordering::AcqRel, Ordering::Relaxed);
+    }
+    fn link_ordered(&self, node: *mut Node<T>, success: Ordering, failure: Ordering) {
+        // counted before it can be popped, so `len` never underflows
+        self.len.fetch_add(1, Ordering::Relaxed);
+        loop {
+            let head = self.head.load(failure);
+            unsafe { &*node }
+                .next
+                .store(Head::untag(head), Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange_weak(head, node, success, failure)
+                .is_ok()
+            {
+                break;
+            }
+        }
+    }
+    /// Move every element of `other` on top of this stack, keeping their
+    /// order.
+    ///
+    /// Walks `other` to find its tail, then links the whole chain in with a
+    /// single CAS, no node is reallocated.
+    pub fn append(&self, other: AtomicStack<T>) {
+        let first = other.head.swap(ptr::null_mut(), Ordering::Acquire);
+        if first.is_null() {
+            return;
+        }
+        let count = other.len.swap(0, Ordering::Relaxed);
+        let mut last = first;
+        loop {
+            let next = unsafe { &*last }.next.load(Ordering::Relaxed);
+            if next.is_null() {
+                break;
+            }
+            last = next;
+        }
+        self.len.fetch_add(count, Ordering::Relaxed);
         loop {
             let head = self.head.load(Ordering::Relaxed);
-            node.next = AtomicPtr::new(head);
+            unsafe { &*last }
+                .next
+                .store(Head::untag(head), Ordering::Relaxed);
             if self
                 .head
-                .compare_exchange_weak(head, node, Ordering::AcqRel, Ordering::Relaxed)
+                .compare_exchange_weak(head, first, Ordering::AcqRel, Ordering::Relaxed)
                 .is_ok()
             {
                 break;
             }
         }
-
-        unsafe { &*node.data }
     }
+    /// Stack of `v`'s elements pushed front to back, so the last one ends
+    /// up on top and [`AtomicStack::into_vec`] returns them reversed.
+    ///
+    /// The stack is private until returned, so the chain is linked without
+    /// any CAS.
+    pub fn from_vec(v: Vec<T>) -> Self {
+        let stack = Self::default();
+        let len = v.len();
+        let mut head = ptr::null_mut();
+        for value in v {
+            let node = stack.alloc_node(Box::into_raw(Box::new(value)));
+            unsafe { &*node }.next.store(head, Ordering::Relaxed);
+            head = node;
+        }
+        stack.head.0.store(head, Ordering::Relaxed);
+        stack.len.store(len, Ordering::Relaxed);
+        stack
+    }
+    /// The elements head-to-tail, i.e. the most recently pushed first.
+    pub fn into_vec(self) -> Vec<T> {
+        let mut v = Vec::with_capacity(self.len());
+        v.extend(self);
+        v
+    }
+    /// Split the elements into those `f` holds for and the rest, relinking
+    /// the nodes without reallocating. Both stacks keep the elements'
+    /// head-to-tail order.
+    pub fn partition<F: FnMut(&T) -> bool>(self, mut f: F) -> (AtomicStack<T>, AtomicStack<T>) {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        self.len.store(0, Ordering::Relaxed);
+        let outputs = [AtomicStack::default(), AtomicStack::default()];
+        let mut tails = [ptr::null_mut::<Node<T>>(); 2];
+        while !node.is_null() {
+            let next = unsafe { &*node }.next.load(Ordering::Relaxed);
+            let side = usize::from(!f(unsafe { &*(*node).data }));
+            unsafe { &*node }
+                .next
+                .store(ptr::null_mut(), Ordering::Relaxed);
+            // both outputs are still private, the tail is linked directly
+            match unsafe { tails[side].as_ref() } {
+                Some(tail) => tail.next.store(node, Ordering::Relaxed),
+                None => outputs[side].head.0.store(node, Ordering::Relaxed),
+            }
+            tails[side] = node;
+            outputs[side].len.fetch_add(1, Ordering::Relaxed);
+            node = next;
+        }
+        let [matching, rest] = outputs;
+        (matching, rest)
+    }
+    /// Install `other`'s elements in place of this stack's in one step,
+    /// returning a stack that owns the old ones.
+    ///
+    /// Pushers may keep going, each element lands in exactly one of the two
+    /// stacks.
+    ///
+    /// # Safety
+    ///
+    /// Detaching the old elements counts as popping, see
+    /// [`AtomicStack::boxed_pop`].
+    pub unsafe fn swap(&self, other: AtomicStack<T>) -> AtomicStack<T> {
+        let first = other.head.swap(ptr::null_mut(), Ordering::Acquire);
+        // `other` is owned, its length is exact
+        let count = other.len.swap(0, Ordering::Relaxed);
+        // counted before the nodes can be popped, like a push
+        self.len.fetch_add(count, Ordering::Relaxed);
+        let old = self.head.swap(first, Ordering::AcqRel);
+        let old_count = Self::chain_len(old);
+        self.len.fetch_sub(old_count, Ordering::Relaxed);
+        AtomicStack {
+            head: Head(AtomicPtr::new(old)),
+            is_taken: Default::default(),
+            len: AtomicUsize::new(old_count),
+            pool: None,
+        }
+    }
+    /// Detach every element at once, leaving the stack empty. Cheaper than
+    /// popping them one by one under contention.
+    ///
+    /// The length of the detached stack is exact even while pushes race the
+    /// detach, see [`AtomicStack::swap`].
+    ///
+    /// # Safety
+    ///
+    /// See [`AtomicStack::swap`].
+    pub unsafe fn pop_all(&self) -> AtomicStack<T> {
+        let first = self.head.swap(ptr::null_mut(), Ordering::AcqRel);
+        let count = Self::chain_len(first);
+        self.len.fetch_sub(count, Ordering::Relaxed);
+        AtomicStack {
+            head: Head(AtomicPtr::new(first)),
+            is_taken: Default::default(),
+            len: AtomicUsize::new(count),
+            pool: None,
+        }
+    }
+    /// Nodes from `node` down, counted rather than read from `len`, which
+    /// pushes increment before linking. The chain must be detached.
+    fn chain_len(mut node: *mut Node<T>) -> usize {
+        let mut count = 0;
+        while !node.is_null() {
+            count += 1;
+            node = unsafe { &*node }.next.load(Ordering::Relaxed);
+        }
+        count
+    }
+    /// Like [`AtomicStack::pop_all`], but safe: the popping rights are
+    /// held while detaching, waiting for any other [`StackGuard`] to drop
+    /// first, so no reference handed out under a guard is left dangling.
+    pub fn take(&self) -> AtomicStack<T> {
+        let _guard = self.own();
+        // the guard rules out poppers, like `AtomicStack::reverse`
+        unsafe { self.pop_all() }
+    }
+    /// # Safety
+    ///
+    /// The popped node is freed immediately, while a concurrent `boxed_pop` may
+    /// still be reading its `next` pointer. Caller must ensure:
+    ///
+    /// - at most one thread pops from the stack at a time (pushes may still
+    ///   run concurrently), and
+    /// - no [`StackGuard`] of this stack is alive except one held by the
+    ///   popping thread itself, so references handed out under another
+    ///   guard (e.g. by [`QueueIterator`] or [`AtomicStack::peek`]) stay
+    ///   valid. References handed out under the popper's own guard must not
+    ///   be used once their element is popped.
+    ///
+    /// Popping only while holding [`AtomicStack::own`] satisfies both, even
+    /// with several popping threads.
     pub unsafe fn boxed_pop(&self) -> Option<Box<T>> {
-        let popping_node_raw = self.head.load(Ordering::Relaxed);
+        self.pop_ordered(Ordering::AcqRel, Ordering::Acquire)
+    }
+    /// Like [`AtomicStack::boxed_pop`], with the orderings of the CAS
+    /// unlinking the head given explicitly, as in
+    /// [`AtomicPtr::compare_exchange`]. The head is loaded with `failure`.
+    ///
+    /// The popper reads the head node before the CAS, so both must be
+    /// `Acquire` or stronger when pushes run on other threads. `Relaxed` is
+    /// only sound if every push of the popped nodes is otherwise ordered
+    /// before the pop, e.g. it ran on the same thread.
+    ///
+    /// # Safety
+    ///
+    /// See [`AtomicStack::boxed_pop`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `failure` is `Release` or `AcqRel`.
+    pub unsafe fn pop_ordered(&self, success: Ordering, failure: Ordering) -> Option<Box<T>> {
+        loop {
+            if let Ok(popped) = self.pop_from(self.head.load(failure), success, failure) {
+                return popped;
+            }
+        }
+    }
+    /// Like [`AtomicStack::boxed_pop`], but make a single attempt and give
+    /// up if another thread changed the head in the meantime, so the caller
+    /// can decide whether to retry, back off or do something else.
+    ///
+    /// # Safety
+    ///
+    /// See [`AtomicStack::boxed_pop`].
+    pub unsafe fn try_pop(&self) -> Result<Option<Box<T>>, Contended> {
+        self.pop_from(
+            self.head.load(Ordering::Acquire),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+    }
+    /// Pop `head` as loaded with `failure`, unless the head changed since.
+    unsafe fn pop_from(
+        &self,
+        head: *mut Node<T>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Option<Box<T>>, Contended> {
+        let popping_node_raw = Head::untag(head);
         if popping_node_raw.is_null() {
-            None
-        } else {
+            return Ok(None);
+        }
+        let popping_node = unsafe { &*popping_node_raw };
+        let next_node = popping_node.next.load(Ordering::Relaxed);
+        self.head
+            .compare_exchange(head, next_node, success, failure)
+            .map_err(|_| Contended)?;
+        self.len.fetch_sub(1, Ordering::Relaxed);
+
+        let data = popping_node.data;
+        self.free_node(popping_node_raw);
+        Ok(Some(Box::from_raw(data)))
+    }
+    /// Pop the head only if `pred` holds for it.
+    ///
+    /// If the head changes before the pop lands, `pred` is evaluated again on
+    /// the new head.
+    ///
+    /// # Safety
+    ///
+    /// See [`AtomicStack::boxed_pop`].
+    pub unsafe fn pop_if<F: Fn(&T) -> bool>(&self, pred: F) -> Option<Box<T>> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let popping_node_raw = Head::untag(head);
+            if popping_node_raw.is_null() {
+                return None;
+            }
             let popping_node = unsafe { &*popping_node_raw };
+            if !pred(unsafe { &*popping_node.data }) {
+                return None;
+            }
             let next_node = popping_node.next.load(Ordering::Relaxed);
-
             if self
                 .head
-                .compare_exchange(
-                    popping_node_raw,
-                    next_node,
-                    Ordering::AcqRel,
-                    Ordering::Relaxed,
-                )
-                .is_err()
+                .compare_exchange(head, next_node, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
             {
-                return self.boxed_pop();
+                self.len.fetch_sub(1, Ordering::Relaxed);
+                let data = popping_node.data;
+                self.free_node(popping_node_raw);
+                return Some(Box::from_raw(data));
             }
-
-            let popping_node = unsafe { Box::from_raw(popping_node_raw) };
-            Some(Box::from_raw(popping_node.data))
         }
     }
+    /// # Safety
+    ///
+    /// See [`AtomicStack::boxed_pop`].
     pub unsafe fn pop(&self) -> Option<T>
     where
         T: Copy,
     {
-        self.boxed_pop().map(|x| x.as_ref().clone())
+        self.boxed_pop().map(|x| *x)
+    }
+    /// Pop elements until the stack is observed empty.
+    ///
+    /// Producers may keep pushing while draining, those elements are either
+    /// yielded by this drain or left in the stack. A drain returning `None`
+    /// may yield again once more elements are pushed. No [`StackGuard`] is
+    /// needed.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`AtomicStack::boxed_pop`], for the whole lifetime of the
+    /// returned [`Drain`].
+    pub unsafe fn drain(&self) -> Drain<'_, T> {
+        Drain { stack: self }
+    }
+    /// Number of nodes in the stack.
+    ///
+    /// The counter is maintained with relaxed atomics and is only advisory:
+    /// under contention it may momentarily lag behind the true state.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+    /// See [`AtomicStack::len`].
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Top element of the stack, the guard keeps it from being popped.
+    ///
+    /// The element is borrowed from the guard, so it can't be used once the
+    /// guard drops and pops may resume:
+    ///
+    /// ```compile_fail
+    /// use atomic::gc::stack::AtomicStack;
+    ///
+    /// let stack = AtomicStack::default();
+    /// stack.push(1);
+    /// let guard = stack.try_own().unwrap();
+    /// let top = stack.peek(&guard);
+    /// drop(guard);
+    /// assert_eq!(top, Some(&1));
+    /// ```
+    pub fn peek<'g>(&'g self, _guard: &'g StackGuard<T>) -> Option<&'g T> {
+        let head = self.head.load_ptr(Ordering::Acquire);
+        if head.is_null() {
+            None
+        } else {
+            Some(unsafe { &*(*head).data })
+        }
+    }
+    pub fn iter<'g>(&'g self, _guard: &'g StackGuard<T>) -> QueueIterator<'g, T> {
+        QueueIterator {
+            _stack: self,
+            next: self.head.load_ptr(Ordering::Acquire),
+        }
     }
-    pub fn into_iter<'a>(&'a self, _guard: &StackGuard<T>) -> QueueIterator<'a, T> {
+    /// Like [`AtomicStack::iter`], but `&mut self` already rules out any
+    /// other access, so no [`StackGuard`] is needed.
+    pub fn iter_unsync(&mut self) -> QueueIterator<'_, T> {
         QueueIterator {
             _stack: self,
-            next: self.head.load(Ordering::Relaxed),
+            next: self.head.load_ptr(Ordering::Relaxed),
+        }
+    }
+    /// Call `f` on every element of the guarded stack, top to bottom.
+    pub fn for_each<F: FnMut(&T)>(&self, guard: &StackGuard<T>, f: F) {
+        self.iter(guard).for_each(f);
+    }
+    /// Collect `f` of every element of the guarded stack, top to bottom.
+    pub fn map_collect<U, F: FnMut(&T) -> U>(&self, guard: &StackGuard<T>, f: F) -> Vec<U> {
+        // the iterator's exact length sizes the allocation
+        self.iter(guard).map(f).collect()
+    }
+    /// Fold every element of the guarded stack into an accumulator, top to
+    /// bottom.
+    pub fn fold<B, F: FnMut(B, &T) -> B>(&self, guard: &StackGuard<T>, init: B, f: F) -> B {
+        self.iter(guard).fold(init, f)
+    }
+    /// Count the elements of the guarded stack matching `pred`, without
+    /// collecting them.
+    pub fn count_where<F: Fn(&T) -> bool>(&self, guard: &StackGuard<T>, pred: F) -> usize {
+        self.iter(guard).filter(|value| pred(value)).count()
+    }
+    /// Element at `index` counting from the top, in O(`index`).
+    pub fn get<'g>(&'g self, guard: &'g StackGuard<T>, index: usize) -> Option<&'g T> {
+        self.iter(guard).nth(index)
+    }
+    /// Index counting from the top of the first element matching `pred`,
+    /// e.g. to [`AtomicStack::split_off_at`] it.
+    pub fn position<F: Fn(&T) -> bool>(&self, guard: &StackGuard<T>, pred: F) -> Option<usize> {
+        self.iter(guard).position(pred)
+    }
+    /// Whether some element of the guarded stack equals `value`, stopping at
+    /// the first match.
+    pub fn contains(&self, guard: &StackGuard<T>, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter(guard).any(|element| element == value)
+    }
+    /// Remove every element for which `f` returns `false`, freeing it.
+    ///
+    /// `f` is called once per visited element. Elements pushed while
+    /// retaining may or may not be visited. Only the head needs a CAS since
+    /// pushers never touch the rest of the chain.
+    ///
+    /// # Safety
+    ///
+    /// No reference handed out under `guard`, e.g. by
+    /// [`AtomicStack::iter`] or [`AtomicStack::peek`], may be alive.
+    pub unsafe fn retain<F: FnMut(&T) -> bool>(&self, _guard: &StackGuard<T>, mut f: F) {
+        let mut prev = loop {
+            let head = self.head.load(Ordering::Acquire);
+            let node = Head::untag(head);
+            if node.is_null() {
+                return;
+            }
+            if f(&*(*node).data) {
+                break node;
+            }
+            let next = (*node).next.load(Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.remove_node(node);
+                continue;
+            }
+            // only pushes move the head while guarded, so `node` is still
+            // linked below them: unlink it there rather than asking `f` again
+            let mut above = self.head.load_ptr(Ordering::Acquire);
+            while (*above).next.load(Ordering::Relaxed) != node {
+                above = (*above).next.load(Ordering::Relaxed);
+            }
+            (*above).next.store(next, Ordering::Relaxed);
+            self.remove_node(node);
+            break above;
+        };
+        let mut node = (*prev).next.load(Ordering::Relaxed);
+        while !node.is_null() {
+            let next = (*node).next.load(Ordering::Relaxed);
+            if f(&*(*node).data) {
+                prev = node;
+            } else {
+                (*prev).next.store(next, Ordering::Relaxed);
+                self.remove_node(node);
+            }
+            node = next;
+        }
+    }
+    /// Free `node` and its element once it was unlinked by
+    /// [`AtomicStack::retain`].
+    unsafe fn remove_node(&self, node: *mut Node<T>) {
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        let data = (*node).data;
+        self.free_node(node);
+        drop(Box::from_raw(data));
+    }
+    /// Call `f` on every element of the guarded stack, top to bottom, with
+    /// mutable access.
+    ///
+    /// Elements pushed meanwhile may or may not be visited.
+    ///
+    /// # Safety
+    ///
+    /// No other reference to the elements may be alive, neither handed out
+    /// under `guard` nor returned by a push: the guard only excludes
+    /// poppers.
+    pub unsafe fn map_in_place<F: FnMut(&mut T)>(&self, _guard: &StackGuard<T>, mut f: F) {
+        let mut node = self.head.load_ptr(Ordering::Acquire);
+        while !node.is_null() {
+            f(&mut *(*node).data);
+            node = (*node).next.load(Ordering::Relaxed);
+        }
+    }
+    /// Remove the elements equal to the one above them, freeing them.
+    ///
+    /// Elements pushed meanwhile may or may not be compared, see
+    /// [`AtomicStack::retain`].
+    ///
+    /// # Safety
+    ///
+    /// See [`AtomicStack::retain`].
+    pub unsafe fn dedup_adjacent(&self, guard: &StackGuard<T>)
+    where
+        T: PartialEq,
+    {
+        // kept elements are never freed by `retain`
+        let mut above: *const T = ptr::null();
+        self.retain(guard, |value| {
+            let duplicate = !above.is_null() && *above == *value;
+            if !duplicate {
+                above = value;
+            }
+            !duplicate
+        })
+    }
+    /// Reverse the guarded stack in place by relinking its nodes, so the
+    /// bottom element ends up on top.
+    ///
+    /// Elements pushed while reversing stay above the reversed ones.
+    ///
+    /// The guard is borrowed mutably, so no iterator borrowed from it sees
+    /// the chain being relinked:
+    ///
+    /// ```compile_fail
+    /// use atomic::gc::stack::AtomicStack;
+    ///
+    /// let stack: AtomicStack<usize> = (0..3).collect();
+    /// let mut guard = stack.try_own().unwrap();
+    /// let iter = stack.iter(&guard);
+    /// stack.reverse(&mut guard);
+    /// iter.len();
+    /// ```
+    pub fn reverse(&self, _guard: &mut StackGuard<T>) {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        if node.is_null() {
+            return;
+        }
+        let mut reversed = ptr::null_mut();
+        while !node.is_null() {
+            let next = unsafe { &*node }.next.swap(reversed, Ordering::Relaxed);
+            reversed = node;
+            node = next;
+        }
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let mut bottom = Head::untag(head);
+            if bottom.is_null() {
+                if self
+                    .head
+                    .compare_exchange(head, reversed, Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    break;
+                }
+                continue;
+            }
+            // pushers only ever touch the head, the nodes below are ours
+            loop {
+                let next = unsafe { &*bottom }.next.load(Ordering::Relaxed);
+                if next.is_null() {
+                    break;
+                }
+                bottom = next;
+            }
+            unsafe { &*bottom }.next.store(reversed, Ordering::Release);
+            break;
+        }
+    }
+    /// Detach the elements from index `n` on, counting from the top, into a
+    /// stack of their own. Empty if the stack has no more than `n` elements.
+    ///
+    /// Elements pushed meanwhile stay in `self`, unless `n` is `0` where the
+    /// split is a [`AtomicStack::pop_all`].
+    ///
+    /// # Safety
+    ///
+    /// See [`AtomicStack::retain`].
+    pub unsafe fn split_off_at(&self, _guard: &StackGuard<T>, n: usize) -> AtomicStack<T> {
+        if n == 0 {
+            return self.pop_all();
+        }
+        let mut last = self.head.load_ptr(Ordering::Acquire);
+        for _ in 1..n {
+            if last.is_null() {
+                break;
+            }
+            last = (*last).next.load(Ordering::Relaxed);
+        }
+        let Some(last) = last.as_ref() else {
+            return AtomicStack::default();
+        };
+        // pushers only ever touch the head, so the rest of the chain is ours
+        let first = last.next.swap(ptr::null_mut(), Ordering::Relaxed);
+        let count = Self::chain_len(first);
+        self.len.fetch_sub(count, Ordering::Relaxed);
+        AtomicStack {
+            head: Head(AtomicPtr::new(first)),
+            is_taken: Default::default(),
+            len: AtomicUsize::new(count),
+            pool: None,
         }
     }
-    pub unsafe fn try_own(&self) -> Option<StackGuard<T>> {
+    /// Iterator over the guarded stack that can be walked from both ends.
+    ///
+    /// Construction walks the whole chain once to collect the elements, so it
+    /// costs O(n) time and memory.
+    pub fn snapshot<'g>(&'g self, guard: &'g StackGuard<T>) -> Snapshot<'g, T> {
+        Snapshot(self.iter(guard).collect::<Vec<_>>().into_iter())
+    }
+    /// Take exclusive ownership of the stack's nodes for reading, `None` if
+    /// some other guard is alive.
+    ///
+    /// Nodes stay alive for the guard's lifetime since only its holder may
+    /// pop meanwhile, see the contract of [`AtomicStack::boxed_pop`].
+    pub fn try_own(&self) -> Option<StackGuard<'_, T>> {
         if self
             .is_taken
             .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
@@ -124,6 +1138,17 @@ impl<T> AtomicStack<T> {
             None
         }
     }
+    /// Like [`AtomicStack::try_own`], but wait for the other guard to drop,
+    /// backing off exponentially and then yielding the thread.
+    pub fn own(&self) -> StackGuard<'_, T> {
+        let mut backoff = sync::Backoff::default();
+        loop {
+            match self.try_own() {
+                Some(guard) => return guard,
+                None => backoff.snooze(),
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -132,6 +1157,20 @@ pub struct QueueIterator<'a, T> {
     next: *mut Node<T>,
 }
 
+// only hands out `&T`
+unsafe impl<'a, T: Sync> Send for QueueIterator<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for QueueIterator<'a, T> {}
+
+// the cursor is a plain pointer, valid as long as the guard pins the nodes
+impl<'a, T> Clone for QueueIterator<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            _stack: self._stack,
+            next: self.next,
+        }
+    }
+}
+
 impl<'a, T> Iterator for QueueIterator<'a, T> {
     type Item = &'a T;
 
@@ -144,27 +1183,157 @@ impl<'a, T> Iterator for QueueIterator<'a, T> {
             Some(unsafe { &*node.data })
         }
     }
+    /// Exact, the guard keeps the remaining chain from changing. Counting
+    /// walks it, so it costs O(n).
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let mut len = 0;
+        let mut next = self.next;
+        while !next.is_null() {
+            len += 1;
+            next = unsafe { &*next }.next.load(Ordering::Acquire);
+        }
+        (len, Some(len))
+    }
 }
 
-#[cfg(test)]
-pub mod test {
-    use std::{sync::atomic::Ordering, thread};
+impl<'a, T> ExactSizeIterator for QueueIterator<'a, T> {}
 
-    use super::AtomicStack;
+/// Double-ended iterator, see [`AtomicStack::snapshot`].
+#[derive(Debug)]
+pub struct Snapshot<'a, T>(vec::IntoIter<&'a T>);
 
-    #[test]
-    fn internal_stack_empty() {
-        let stack: AtomicStack<usize> = AtomicStack::default();
-        assert_eq!(unsafe { stack.pop() }, None);
+impl<'a, T> Iterator for Snapshot<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
     }
-    #[test]
-    fn internal_stack_one() {
-        let stack = AtomicStack::default();
+}
+
+impl<'a, T> DoubleEndedIterator for Snapshot<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back()
+    }
+}
+
+/// Iterator popping owned elements, see [`AtomicStack::drain`].
+#[derive(Debug)]
+pub struct Drain<'a, T> {
+    stack: &'a AtomicStack<T>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe { self.stack.boxed_pop() }.map(|x| *x)
+    }
+}
+
+impl<T> FromIterator<T> for AtomicStack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let stack = Self::default();
+        (&stack).extend(iter);
+        stack
+    }
+}
+
+impl<T> Extend<T> for &AtomicStack<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.boxed_push(Box::new(value));
+        }
+    }
+}
+
+impl<T> Extend<T> for AtomicStack<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        (&*self).extend(iter)
+    }
+}
+
+impl<T> IntoIterator for AtomicStack<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let next = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        self.len.store(0, Ordering::Relaxed);
+        IntoIter { next }
+    }
+}
+
+/// Owning iterator over an [`AtomicStack`], yielding elements head-to-tail.
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    next: *mut Node<T>,
+}
+
+// owns the remaining nodes
+unsafe impl<T: Send> Send for IntoIter<T> {}
+unsafe impl<T: Sync> Sync for IntoIter<T> {}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            None
+        } else {
+            let node = unsafe { Box::from_raw(self.next) };
+            self.next = node.next.load(Ordering::Relaxed);
+            Some(*unsafe { Box::from_raw(node.data) })
+        }
+    }
+}
+
+impl<T> Drop for IntoIter<T> {
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::{
+        cell::Cell,
+        iter, ptr,
+        sync::{
+            atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+    };
+
+    use super::{AtomicStack, Contended, Head, IntoIter, Node, QueueIterator, StackGuard};
+    use crate::test_alloc;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn internal_stack_send_sync() {
+        assert_send::<AtomicStack<i32>>();
+        assert_sync::<AtomicStack<i32>>();
+        assert_send::<StackGuard<'_, i32>>();
+        assert_send::<QueueIterator<'_, i32>>();
+        assert_send::<IntoIter<i32>>();
+        // `Send` but not `Sync` elements can still be moved across threads
+        assert_send::<AtomicStack<Cell<i32>>>();
+    }
+    #[test]
+    fn internal_stack_empty() {
+        let stack: AtomicStack<usize> = AtomicStack::default();
+        assert_eq!(unsafe { stack.pop() }, None);
+    }
+    #[test]
+    fn internal_stack_one() {
+        let stack = AtomicStack::default();
         stack.push(0_usize);
         stack.push(0_usize);
 
         // trigger miri's detection
-        unsafe { &*stack.head.load(Ordering::Relaxed) };
+        let _ = unsafe { &*stack.head.load_ptr(Ordering::Relaxed) };
 
         assert_eq!(0, unsafe { stack.pop().unwrap() });
         assert_eq!(0, unsafe { stack.pop().unwrap() });
@@ -175,16 +1344,872 @@ pub mod test {
         for i in 0_usize..10 {
             stack.push(i);
         }
-        let guard = unsafe { stack.try_own().unwrap() };
-        let mut iter = stack.into_iter(&guard);
+        let guard = stack.try_own().unwrap();
+        let mut iter = stack.iter(&guard);
         for i in (0_usize..10).rev() {
             assert_eq!(i, *iter.next().unwrap())
         }
+        drop(guard);
         for i in (0_usize..10).rev() {
             assert_eq!(i, unsafe { stack.pop().unwrap() })
         }
     }
     #[test]
+    fn internal_stack_len() {
+        let stack = AtomicStack::default();
+        assert!(stack.is_empty());
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        stack.push(1_usize);
+                    }
+                });
+            }
+            s.spawn(|| {
+                let mut popped = 0;
+                while popped < 800 {
+                    if unsafe { stack.pop() }.is_some() {
+                        popped += 1;
+                    }
+                }
+            });
+        });
+        assert_eq!(stack.len(), 0);
+        assert!(stack.is_empty());
+    }
+    #[test]
+    fn internal_stack_peek() {
+        let stack = AtomicStack::default();
+        {
+            let guard = stack.try_own().unwrap();
+            assert_eq!(stack.peek(&guard), None);
+        }
+        stack.push(1_usize);
+        let guard = stack.try_own().unwrap();
+        assert_eq!(stack.peek(&guard), Some(&1));
+        let peeked = *stack.peek(&guard).unwrap();
+        drop(guard);
+        assert_eq!(Some(peeked), unsafe { stack.pop() });
+        assert!(stack.is_empty());
+    }
+    #[test]
+    fn internal_stack_guard_deref() {
+        let stack: AtomicStack<usize> = (0..3).collect();
+        let guard = stack.try_own().unwrap();
+        assert_eq!(guard.len(), 3);
+        assert_eq!(guard.iter(&guard).copied().collect::<Vec<_>>(), [2, 1, 0]);
+    }
+    #[test]
+    fn internal_stack_try_own() {
+        let stack: AtomicStack<usize> = (0..3).collect();
+        let guard = stack.try_own().unwrap();
+        assert!(stack.try_own().is_none());
+        assert_eq!(stack.iter(&guard).count(), 3);
+        drop(guard);
+        assert!(stack.try_own().is_some());
+    }
+    #[test]
+    fn internal_stack_snapshot() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        let mut snapshot = stack.snapshot(&guard);
+        let mut order = Vec::new();
+        for i in 0..10 {
+            let x = if i % 2 == 0 {
+                snapshot.next()
+            } else {
+                snapshot.next_back()
+            };
+            order.push(*x.unwrap());
+        }
+        assert_eq!(snapshot.next(), None);
+        assert_eq!(order, [9, 0, 8, 1, 7, 2, 6, 3, 5, 4]);
+    }
+    #[test]
+    fn internal_stack_clone() {
+        let stack: AtomicStack<String> = (0..5).map(|x| x.to_string()).collect();
+        let cloned = stack.clone();
+        drop(stack);
+        assert_eq!(cloned.len(), 5);
+        assert_eq!(
+            cloned.into_iter().collect::<Vec<_>>(),
+            ["4", "3", "2", "1", "0"]
+        );
+    }
+    #[test]
+    fn internal_stack_append() {
+        let stack: AtomicStack<usize> = (5..10).collect();
+        stack.append((0..5).collect());
+        stack.append(AtomicStack::default());
+        assert_eq!(stack.len(), 10);
+        assert_eq!(
+            stack.into_iter().collect::<Vec<_>>(),
+            [4, 3, 2, 1, 0, 9, 8, 7, 6, 5]
+        );
+    }
+    #[test]
+    fn internal_stack_into_iter() {
+        let stack = AtomicStack::default();
+        for i in 0_usize..10 {
+            stack.push(i);
+        }
+        let v: Vec<usize> = stack.into_iter().collect();
+        assert_eq!(v, (0_usize..10).rev().collect::<Vec<_>>());
+    }
+    #[test]
+    fn internal_stack_drain() {
+        let stack = AtomicStack::default();
+        let done = AtomicBool::new(false);
+        let mut drained = thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0_usize..1000 {
+                    stack.push(i);
+                }
+                done.store(true, Ordering::Release);
+            });
+            let mut drained = Vec::new();
+            let mut drain = unsafe { stack.drain() };
+            loop {
+                let finished = done.load(Ordering::Acquire);
+                drained.extend(&mut drain);
+                if finished {
+                    break drained;
+                }
+            }
+        });
+        drained.sort();
+        assert_eq!(drained, (0_usize..1000).collect::<Vec<_>>());
+        assert!(stack.is_empty());
+    }
+    #[test]
+    fn internal_stack_zst() {
+        let stack = AtomicStack::default();
+        for _ in 0..100 {
+            stack.push(());
+        }
+        assert_eq!(stack.len(), 100);
+        let mut count = 0;
+        while let Some(()) = unsafe { stack.pop() } {
+            count += 1;
+        }
+        assert_eq!(count, 100);
+        (&stack).extend([(); 10]);
+        assert_eq!(stack.into_iter().count(), 10);
+    }
+    #[test]
+    fn internal_stack_pop_if() {
+        let stack: AtomicStack<usize> = (0..3).collect();
+        assert_eq!(unsafe { stack.pop_if(|x| *x == 0) }, None);
+        assert_eq!(stack.len(), 3);
+        assert_eq!(unsafe { stack.pop_if(|x| *x == 2) }, Some(Box::new(2)));
+        assert_eq!(stack.len(), 2);
+
+        let pushed = Cell::new(false);
+        let popped = unsafe {
+            stack.pop_if(|x| {
+                if !pushed.replace(true) {
+                    stack.push(10);
+                }
+                *x != 0
+            })
+        };
+        assert_eq!(popped, Some(Box::new(10)));
+        assert_eq!(unsafe { stack.pop() }, Some(1));
+    }
+    #[test]
+    fn internal_stack_retain() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        unsafe { stack.retain(&guard, |x| x % 2 == 0) };
+        assert_eq!(stack.len(), 5);
+        unsafe { stack.retain(&guard, |x| *x != 8) };
+        drop(guard);
+        let v: Vec<usize> = unsafe { stack.drain() }.collect();
+        assert_eq!(v, [6, 4, 2, 0]);
+    }
+    #[test]
+    fn internal_stack_retain_pushing() {
+        for _ in 0..100 {
+            let stack: AtomicStack<usize> = (0..1000).collect();
+            let calls: Vec<AtomicUsize> = (0..2000).map(|_| AtomicUsize::new(0)).collect();
+            let guard = stack.try_own().unwrap();
+            // removing from the top keeps racing the pusher for the head
+            thread::scope(|s| {
+                s.spawn(|| {
+                    for i in 1000..2000 {
+                        stack.push(i);
+                    }
+                });
+                unsafe {
+                    stack.retain(&guard, |&x| {
+                        calls[x].fetch_add(1, Ordering::Relaxed);
+                        x >= 1000
+                    })
+                };
+            });
+            drop(guard);
+            assert!(calls[..1000].iter().all(|n| n.load(Ordering::Relaxed) == 1));
+            assert!(calls[1000..].iter().all(|n| n.load(Ordering::Relaxed) <= 1));
+            let mut left: Vec<usize> = unsafe { stack.drain() }.collect();
+            left.sort();
+            assert_eq!(left, (1000..2000).collect::<Vec<_>>());
+        }
+    }
+    #[test]
+    fn internal_stack_map_in_place() {
+        let stack: AtomicStack<usize> = (0..5).collect();
+        let guard = stack.try_own().unwrap();
+        unsafe { stack.map_in_place(&guard, |x| *x *= 2) };
+        drop(guard);
+        let v: Vec<usize> = unsafe { stack.drain() }.collect();
+        assert_eq!(v, [8, 6, 4, 2, 0]);
+    }
+    #[test]
+    fn internal_stack_dedup_adjacent() {
+        let stack: AtomicStack<usize> = [3, 2, 2, 2, 1, 1].into_iter().collect();
+        let guard = stack.try_own().unwrap();
+        unsafe { stack.dedup_adjacent(&guard) };
+        assert_eq!(stack.len(), 3);
+        drop(guard);
+        let v: Vec<usize> = unsafe { stack.drain() }.collect();
+        assert_eq!(v, [1, 2, 3]);
+    }
+    #[test]
+    fn internal_stack_split_off_at() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        let tail = unsafe { stack.split_off_at(&guard, 4) };
+        assert_eq!((stack.len(), tail.len()), (4, 6));
+        assert!(stack.iter(&guard).copied().eq([9, 8, 7, 6]));
+        assert!(tail.into_iter().eq((0..6).rev()));
+
+        assert!(unsafe { stack.split_off_at(&guard, 4) }.is_empty());
+        assert!(unsafe { stack.split_off_at(&guard, 10) }.is_empty());
+        let all = unsafe { stack.split_off_at(&guard, 0) };
+        assert!(stack.is_empty());
+        assert!(all.into_iter().eq([9, 8, 7, 6]));
+    }
+    #[test]
+    fn internal_stack_reverse() {
+        let stack: AtomicStack<usize> = (0..5).collect();
+        let mut guard = stack.try_own().unwrap();
+        stack.reverse(&mut guard);
+        assert_eq!(stack.len(), 5);
+        drop(guard);
+        let v: Vec<usize> = unsafe { stack.drain() }.collect();
+        assert_eq!(v, [0, 1, 2, 3, 4]);
+
+        let stack = AtomicStack::<usize>::default();
+        stack.reverse(&mut stack.try_own().unwrap());
+        assert!(stack.is_empty());
+    }
+    #[test]
+    fn internal_stack_reverse_while_pushing() {
+        let stack = AtomicStack::default();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..10_000 {
+                    stack.push(i);
+                }
+            });
+            for _ in 0..100 {
+                stack.reverse(&mut stack.try_own().unwrap());
+            }
+        });
+        assert_eq!(stack.len(), 10_000);
+        let mut v: Vec<usize> = stack.into_iter().collect();
+        v.sort_unstable();
+        assert!(v.into_iter().eq(0..10_000));
+    }
+    #[test]
+    fn internal_stack_swap() {
+        let stack: AtomicStack<usize> = AtomicStack::default();
+        let mut collected = Vec::new();
+
+        thread::scope(|s| {
+            let pusher = s.spawn(|| {
+                for i in 0..10000 {
+                    stack.push(i);
+                }
+            });
+            while !pusher.is_finished() {
+                let detached = unsafe { stack.swap(AtomicStack::default()) };
+                // exact while pushes race the swap
+                let len = detached.len();
+                let detached = detached.into_vec();
+                assert_eq!(detached.len(), len);
+                collected.extend(detached);
+            }
+        });
+        let rest = unsafe { stack.swap((10000..10003).collect()) };
+        collected.extend(rest);
+
+        collected.sort();
+        assert_eq!(collected, (0..10000).collect::<Vec<_>>());
+        assert_eq!(stack.len(), 3);
+    }
+    #[test]
+    fn internal_stack_debug() {
+        let stack: AtomicStack<usize> = (1..4).collect();
+        assert_eq!(format!("{stack:?}"), "AtomicStack [3, 2, 1]");
+        let guard = stack.try_own().unwrap();
+        assert_eq!(
+            format!("{:?}", *guard),
+            "AtomicStack { len: 3, elements: <locked> }"
+        );
+        drop(guard);
+        assert_eq!(
+            format!("{:?}", AtomicStack::<()>::default()),
+            "AtomicStack []"
+        );
+    }
+    #[test]
+    fn internal_stack_pop_all() {
+        let stack: AtomicStack<usize> = AtomicStack::default();
+        let mut total = 0;
+
+        let take = || {
+            let taken = unsafe { stack.pop_all() };
+            let len = taken.len();
+            assert_eq!(taken.into_iter().count(), len);
+            len
+        };
+        thread::scope(|s| {
+            let pushers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        for i in 0..1000 {
+                            stack.push(i);
+                        }
+                    })
+                })
+                .collect();
+            while !pushers.iter().all(|pusher| pusher.is_finished()) {
+                total += take();
+            }
+        });
+        total += take();
+
+        assert_eq!(total, 4000);
+        assert!(stack.is_empty());
+    }
+    #[test]
+    fn internal_stack_take() {
+        let stack: AtomicStack<usize> = AtomicStack::default();
+        let mut collected = Vec::new();
+
+        thread::scope(|s| {
+            let pushers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        for i in 0..1000 {
+                            stack.push(i);
+                        }
+                    })
+                })
+                .collect();
+            while !pushers.iter().all(|pusher| pusher.is_finished()) {
+                collected.extend(stack.take());
+            }
+        });
+        collected.extend(stack.take());
+
+        assert_eq!(collected.len(), 4000);
+        assert_eq!(
+            collected.iter().sum::<usize>(),
+            4 * (0..1000).sum::<usize>()
+        );
+        assert!(stack.take().is_empty());
+    }
+    #[test]
+    fn internal_stack_iter_len() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        let mut iter = stack.iter(&guard);
+        assert_eq!(iter.len(), 10);
+        iter.next();
+        assert_eq!(iter.size_hint(), (9, Some(9)));
+
+        stack.push(10);
+        let v: Vec<_> = stack.iter(&guard).collect();
+        assert_eq!(v.len(), 11);
+        assert_eq!(v.capacity(), 11);
+    }
+    #[test]
+    fn internal_stack_iter_unsync() {
+        let mut stack: AtomicStack<usize> = (0..5).collect();
+        let guarded: Vec<_> = {
+            let guard = stack.try_own().unwrap();
+            stack.iter(&guard).copied().collect()
+        };
+        assert!(stack.iter_unsync().copied().eq(guarded));
+        assert_eq!(stack.iter_unsync().len(), 5);
+    }
+    #[test]
+    fn internal_stack_map_collect() {
+        let stack: AtomicStack<usize> = (0..5).collect();
+        let guard = stack.try_own().unwrap();
+        let mapped = stack.map_collect(&guard, |x| format!("#{x}"));
+        assert_eq!(mapped, ["#4", "#3", "#2", "#1", "#0"]);
+        assert_eq!(mapped.capacity(), 5);
+    }
+    #[test]
+    fn internal_stack_cas_head() {
+        let stack = AtomicStack::default();
+        stack.push(1_usize);
+        // increment the top optimistically, a concurrent push gets in first
+        let mut observed = stack.head_ptr();
+        let mut attempts = 0;
+        let pushed = loop {
+            attempts += 1;
+            let guard = stack.try_own().unwrap();
+            let next = stack.peek(&guard).unwrap() + 1;
+            drop(guard);
+            if attempts == 1 {
+                stack.push(10);
+            }
+            match stack.cas_head(observed, next) {
+                Ok(&pushed) => break pushed,
+                Err((_, head)) => observed = head,
+            }
+        };
+        assert_eq!((attempts, pushed), (2, 11));
+        assert_eq!(stack.len(), 3);
+        assert!(stack.into_iter().eq([11, 10, 1]));
+    }
+    #[test]
+    fn internal_stack_vec() {
+        let stack = AtomicStack::from_vec(vec![1, 2, 3]);
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.peek(&stack.try_own().unwrap()), Some(&3));
+        assert_eq!(stack.into_vec(), [3, 2, 1]);
+
+        let v: Vec<usize> = (0..100).collect();
+        let mut round_trip = AtomicStack::from_vec(v.clone()).into_vec();
+        round_trip.reverse();
+        assert_eq!(round_trip, v);
+        assert!(AtomicStack::<usize>::from_vec(Vec::new())
+            .into_vec()
+            .is_empty());
+    }
+    #[test]
+    fn internal_stack_partition() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let (evens, odds) = stack.partition(|x| x % 2 == 0);
+        assert_eq!(evens.len(), 5);
+        assert_eq!(odds.len(), 5);
+        assert!(evens.into_iter().eq([8, 6, 4, 2, 0]));
+        assert!(odds.into_iter().eq([9, 7, 5, 3, 1]));
+    }
+    #[test]
+    fn internal_stack_fold() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        assert_eq!(stack.fold(&guard, 0, |sum, x| sum + x), 45);
+
+        let mut seen = Vec::new();
+        stack.for_each(&guard, |&x| seen.push(x));
+        assert_eq!(seen, (0..10).rev().collect::<Vec<_>>());
+    }
+    #[test]
+    fn internal_stack_count_where() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        assert_eq!(stack.count_where(&guard, |x| x % 2 == 0), 5);
+        assert_eq!(stack.count_where(&guard, |&x| x >= 10), 0);
+    }
+    #[test]
+    fn internal_stack_get() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        assert_eq!(stack.get(&guard, 0), Some(&9));
+        assert_eq!(stack.get(&guard, 3), Some(&6));
+        assert_eq!(stack.get(&guard, 9), Some(&0));
+        assert_eq!(stack.get(&guard, 10), None);
+    }
+    #[test]
+    fn internal_stack_position() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        assert_eq!(stack.position(&guard, |&x| x == 9), Some(0));
+        assert_eq!(stack.position(&guard, |&x| x < 5), Some(5));
+        assert_eq!(stack.position(&guard, |&x| x == 0), Some(9));
+        assert_eq!(stack.position(&guard, |&x| x > 9), None);
+    }
+    #[test]
+    fn internal_stack_contains() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        let guard = stack.try_own().unwrap();
+        assert!(stack.contains(&guard, &3));
+        assert!(!stack.contains(&guard, &10));
+
+        let empty = AtomicStack::<usize>::default();
+        assert!(!empty.contains(&empty.try_own().unwrap(), &0));
+    }
+    #[test]
+    fn internal_stack_push_returning_guard() {
+        let stack = AtomicStack::default();
+        let popped = AtomicBool::new(false);
+        let pushed = stack.push_returning_guard(vec![1, 2, 3]);
+        thread::scope(|s| {
+            s.spawn(|| {
+                // pops only while holding the rights itself
+                let guard = stack.own();
+                let value = unsafe { stack.boxed_pop() }.unwrap();
+                drop(guard);
+                popped.store(true, Ordering::Release);
+                assert_eq!(*value, [1, 2, 3]);
+            });
+            thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!popped.load(Ordering::Acquire));
+            assert_eq!(*pushed, [1, 2, 3]);
+            assert!(stack.iter(pushed.stack_guard()).eq([&vec![1, 2, 3]]));
+            drop(pushed);
+        });
+        assert!(popped.load(Ordering::Acquire));
+        assert!(stack.is_empty());
+    }
+    #[test]
+    fn internal_stack_own() {
+        let stack: AtomicStack<usize> = (0..3).collect();
+        let (taken, released) = (AtomicBool::new(false), AtomicBool::new(false));
+        thread::scope(|s| {
+            s.spawn(|| {
+                let guard = stack.own();
+                taken.store(true, Ordering::Release);
+                thread::sleep(std::time::Duration::from_millis(10));
+                released.store(true, Ordering::Release);
+                drop(guard);
+            });
+            while !taken.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            assert!(stack.try_own().is_none());
+            let guard = stack.own();
+            assert!(released.load(Ordering::Acquire));
+            assert_eq!(stack.iter(&guard).count(), 3);
+        });
+    }
+    #[test]
+    fn internal_stack_iter_clone() {
+        let stack: AtomicStack<usize> = (0..5).collect();
+        let guard = stack.try_own().unwrap();
+        let mut iter = stack.iter(&guard);
+        iter.next();
+        iter.next();
+        let cursor = iter.clone();
+        assert_eq!(cursor.len(), 3);
+        assert!(iter.eq(cursor));
+    }
+    #[test]
+    fn internal_stack_push_raw() {
+        let stack = AtomicStack::default();
+        stack.push(0_usize);
+        let node = stack.push_raw(Box::new(1));
+        assert_eq!(unsafe { &*node }.value(), &1);
+        assert_eq!(unsafe { stack.pop() }, Some(1));
+        assert_eq!(unsafe { stack.pop() }, Some(0));
+    }
+    #[test]
+    #[cfg(feature = "serde")]
+    fn internal_stack_serde() {
+        let stack: AtomicStack<usize> = (0..5).collect();
+        let json = serde_json::to_string(&stack).unwrap();
+        assert_eq!(json, "[4,3,2,1,0]");
+        let back: AtomicStack<usize> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{back:?}"), format!("{stack:?}"));
+
+        let guard = stack.try_own().unwrap();
+        assert!(serde_json::to_string(&*guard).is_err());
+    }
+    #[test]
+    fn internal_stack_collect() {
+        let stack: AtomicStack<usize> = (0..10).collect();
+        (&stack).extend(10..15);
+        assert_eq!(stack.len(), 15);
+        let v: Vec<usize> = stack.into_iter().collect();
+        assert_eq!(v, (0_usize..15).rev().collect::<Vec<_>>());
+    }
+    #[test]
+    fn internal_stack_concurrent_pop() {
+        const PUSHES: usize = if cfg!(miri) { 20 } else { 1000 };
+        let stack = AtomicStack::default();
+        let popped = Mutex::new(Vec::new());
+        thread::scope(|s| {
+            for pusher in 0..2 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..PUSHES {
+                        stack.push(vec![pusher; i + 1]);
+                    }
+                });
+            }
+            // poppers race each other for the popping rights and the pushers
+            // for the head
+            for _ in 0..2 {
+                s.spawn(|| {
+                    while popped.lock().unwrap().len() < 2 * PUSHES {
+                        let guard = stack.own();
+                        let value = unsafe { stack.boxed_pop() };
+                        drop(guard);
+                        if let Some(value) = value {
+                            popped.lock().unwrap().push(*value);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(stack.is_empty());
+        let popped = popped.into_inner().unwrap();
+        assert_eq!(popped.len(), 2 * PUSHES);
+        for pusher in 0..2 {
+            let mut lens: Vec<_> = popped
+                .iter()
+                .filter(|value| value[0] == pusher)
+                .map(Vec::len)
+                .collect();
+            lens.sort_unstable();
+            assert_eq!(lens, (1..=PUSHES).collect::<Vec<_>>());
+        }
+    }
+    #[test]
+    fn internal_stack_contended_pop() {
+        const PUSHERS: usize = 8;
+        const PUSHES: usize = 10_000;
+        let stack = AtomicStack::default();
+        let done = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..PUSHERS {
+                s.spawn(|| {
+                    for i in 0..PUSHES {
+                        stack.push(i);
+                    }
+                    done.fetch_add(1, Ordering::Release);
+                });
+            }
+            // every failed CAS is retried in place, a small stack is enough
+            thread::Builder::new()
+                .stack_size(32 * 1024)
+                .spawn_scoped(s, || {
+                    let mut popped = 0;
+                    while done.load(Ordering::Acquire) < PUSHERS || !stack.is_empty() {
+                        // the only popper
+                        popped += unsafe { stack.boxed_pop() }.is_some() as usize;
+                    }
+                    assert_eq!(popped, PUSHERS * PUSHES);
+                })
+                .unwrap();
+        });
+    }
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn internal_stack_aba() {
+        let pool: Vec<_> = (0..2)
+            .map(|_| {
+                Box::into_raw(Box::new(Node {
+                    next: AtomicPtr::default(),
+                    data: ptr::null_mut::<usize>(),
+                }))
+            })
+            .collect();
+        let (a, b) = (pool[0], pool[1]);
+        let head = Head::default();
+        unsafe { (*a).next.store(b, Ordering::Relaxed) };
+        head.swap(a, Ordering::Relaxed);
+
+        // a stalled popper observes `a -> b`
+        let observed = head.load(Ordering::Relaxed);
+        let next = unsafe { (*Head::untag(observed)).next.load(Ordering::Relaxed) };
+
+        // meanwhile `a` and `b` are popped and `a`'s slot is pushed again
+        for node in [a, b] {
+            let current = head.load(Ordering::Relaxed);
+            let next = unsafe { (*node).next.load(Ordering::Relaxed) };
+            head.compare_exchange(current, next, Ordering::AcqRel, Ordering::Relaxed)
+                .unwrap();
+        }
+        unsafe { (*a).next.store(ptr::null_mut(), Ordering::Relaxed) };
+        let current = head.load(Ordering::Relaxed);
+        head.compare_exchange(current, a, Ordering::AcqRel, Ordering::Relaxed)
+            .unwrap();
+        assert_eq!(head.load_ptr(Ordering::Relaxed), Head::untag(observed));
+
+        // without the tag this would install the popped `b` as head
+        assert!(head
+            .compare_exchange(observed, next, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err());
+        assert_eq!(head.load_ptr(Ordering::Relaxed), a);
+
+        for node in pool {
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+    #[test]
+    fn internal_stack_pool() {
+        let stack = AtomicStack::with_pool(4);
+        for i in 0_usize..8 {
+            stack.push(i);
+        }
+        while unsafe { stack.pop() }.is_some() {}
+        let pool = stack.pool.as_ref().unwrap();
+        assert_eq!(pool.len.load(Ordering::Relaxed), 4);
+        let mut node = pool.head.load_ptr(Ordering::Relaxed);
+        while !node.is_null() {
+            assert!(unsafe { (*node).data }.is_null());
+            node = unsafe { (*node).next.load(Ordering::Relaxed) };
+        }
+
+        for i in 10_usize..13 {
+            stack.push(i);
+        }
+        assert_eq!(stack.pool.as_ref().unwrap().len.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            stack.into_iter().collect::<Vec<_>>(),
+            (10_usize..13).rev().collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn internal_stack_pool_threads() {
+        let stack = AtomicStack::with_pool(4);
+        stack.reserve(4);
+        let pool = stack.pool.as_ref().unwrap();
+        // a second taker allocates instead of racing the first
+        pool.taking.store(true, Ordering::Relaxed);
+        assert!(pool.take().is_none());
+        pool.taking.store(false, Ordering::Relaxed);
+        let node = pool.take().unwrap();
+        unsafe { stack.free_node(node) };
+
+        let mut popped = 0;
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0_usize..1000 {
+                        stack.push(i);
+                    }
+                });
+            }
+            // the only popper, recycled nodes are taken while pushing
+            while popped < 4000 {
+                popped += unsafe { stack.pop() }.is_some() as usize;
+            }
+        });
+        assert!(stack.is_empty());
+        assert!(pool.len.load(Ordering::Relaxed) <= 4);
+    }
+    #[test]
+    fn internal_stack_shrink_to() {
+        let stack = AtomicStack::with_pool(16);
+        let pooled =
+            |stack: &AtomicStack<usize>| stack.pool.as_ref().unwrap().len.load(Ordering::Relaxed);
+        for i in 0_usize..20 {
+            stack.push(i);
+        }
+        for _ in 0..16 {
+            unsafe { stack.pop() };
+        }
+        assert_eq!(pooled(&stack), 16);
+
+        stack.shrink_to(4);
+        assert_eq!(pooled(&stack), 4);
+        stack.shrink_to(0);
+        assert_eq!(pooled(&stack), 0);
+        assert!(stack.pool.as_ref().unwrap().take().is_none());
+
+        // shrinking while another thread pushes and pops
+        thread::scope(|s| {
+            let churn = s.spawn(|| {
+                for i in 0_usize..1000 {
+                    stack.push(i);
+                    // the only popper
+                    unsafe { stack.pop() };
+                }
+            });
+            while !churn.is_finished() {
+                stack.shrink_to(0);
+            }
+        });
+        stack.shrink_to(0);
+        assert_eq!(pooled(&stack), 0);
+        assert_eq!(stack.into_iter().collect::<Vec<_>>(), [3, 2, 1, 0]);
+    }
+    #[test]
+    fn internal_stack_try_push() {
+        let stack = AtomicStack::default();
+        assert_eq!(stack.try_push(1), Ok(&1));
+        for allowed in 0..2 {
+            let (result, live) =
+                test_alloc::live(|| test_alloc::fail_after(allowed, || stack.try_push(2).copied()));
+            assert_eq!(result, Err(2));
+            assert_eq!(live, 0);
+        }
+        assert_eq!(
+            test_alloc::fail_after(2, || stack.try_push(3).copied()),
+            Ok(3)
+        );
+        assert_eq!(stack.len(), 2);
+        assert_eq!(unsafe { stack.pop() }, Some(3));
+
+        // a pooled node needs no allocation
+        let stack = AtomicStack::with_pool(1);
+        stack.reserve(1);
+        assert_eq!(
+            test_alloc::fail_after(1, || stack.try_push(4).copied()),
+            Ok(4)
+        );
+    }
+    #[test]
+    fn internal_stack_ordered() {
+        let stack = AtomicStack::default();
+        for i in 0..3 {
+            // only this thread reads the elements
+            unsafe { stack.push_ordered(Box::new(i), Ordering::Relaxed, Ordering::Relaxed) };
+        }
+        {
+            let guard = stack.try_own().unwrap();
+            assert!(stack.iter(&guard).copied().eq([2, 1, 0]));
+        }
+        let popped: Vec<_> =
+            iter::from_fn(|| unsafe { stack.pop_ordered(Ordering::Relaxed, Ordering::Relaxed) })
+                .map(|x| *x)
+                .collect();
+        assert_eq!(popped, [2, 1, 0]);
+        assert!(stack.is_empty());
+    }
+    #[test]
+    fn internal_stack_try_pop() {
+        let stack = AtomicStack::default();
+        assert_eq!(unsafe { stack.try_pop() }, Ok(None));
+        stack.push(1);
+        assert_eq!(unsafe { stack.try_pop() }, Ok(Some(Box::new(1))));
+
+        // a push lands between loading the head and unlinking it
+        stack.push(2);
+        let head = stack.head.load(Ordering::Acquire);
+        stack.push(3);
+        let popped = unsafe { stack.pop_from(head, Ordering::AcqRel, Ordering::Acquire) };
+        assert_eq!(popped, Err(Contended));
+        assert_eq!(stack.len(), 2);
+        assert_eq!(unsafe { stack.try_pop() }, Ok(Some(Box::new(3))));
+    }
+    #[test]
+    fn internal_stack_reserve() {
+        let stack = AtomicStack::with_pool(4);
+        let pooled =
+            |stack: &AtomicStack<usize>| stack.pool.as_ref().unwrap().len.load(Ordering::Relaxed);
+        stack.reserve(3);
+        assert_eq!(pooled(&stack), 3);
+        stack.reserve(3);
+        assert_eq!(pooled(&stack), 4);
+        stack.push(1);
+        assert_eq!(pooled(&stack), 3);
+
+        let unpooled = AtomicStack::<usize>::default();
+        unpooled.reserve(3);
+        assert!(unpooled.pool.is_none());
+    }
+    #[test]
     #[ignore = "tested, time-consuming"]
     fn internal_stack_multiple() {
         let stack = AtomicStack::default();
@@ -199,3 +2224,35 @@ pub mod test {
         });
     }
 }
+
+#[cfg(all(test, loom))]
+mod loom {
+    use loom::{sync::Arc, thread};
+
+    use super::AtomicStack;
+
+    #[test]
+    fn loom_stack_push_pop() {
+        loom::model(|| {
+            let stack = Arc::new(AtomicStack::default());
+
+            let pusher = {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    stack.push(1_usize);
+                    stack.push(2);
+                })
+            };
+            // the only popper
+            let mut popped: Vec<_> = unsafe { stack.boxed_pop() }
+                .map(|x| *x)
+                .into_iter()
+                .collect();
+            pusher.join().unwrap();
+
+            popped.extend(unsafe { stack.drain() });
+            popped.sort();
+            assert_eq!(popped, [1, 2]);
+        });
+    }
+}
@@ -1,2 +1,12 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod channel;
 pub mod gc;
+pub mod pool;
+pub mod queue;
 pub mod stack;
+mod sync;
+#[cfg(all(test, not(loom)))]
+mod test_alloc;
@@ -0,0 +1,199 @@
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    gc::stack::AtomicStack,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+type Factory<T> = Box<dyn Fn() -> T + Send + Sync>;
+
+/// Lock-free object pool keeping idle objects in an [`AtomicStack`].
+///
+/// At most `capacity` objects are kept idle, the ones returned to a full
+/// pool are dropped.
+pub struct Pool<T> {
+    idle: AtomicStack<T>,
+    /// Idle slots handed out, never below the length of `idle`.
+    claimed: AtomicUsize,
+    /// Held by the single popper of `idle`.
+    popping: AtomicBool,
+    capacity: usize,
+    factory: Factory<T>,
+}
+
+// objects only move in and out of the stack, they are never shared
+unsafe impl<T: Send> Sync for Pool<T> {}
+
+impl<T> fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("idle", &self.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl<T: Default + 'static> Pool<T> {
+    /// Pool constructing objects with [`Default`].
+    pub fn new(capacity: usize) -> Self {
+        Self::with_factory(capacity, T::default)
+    }
+}
+
+impl<T> Pool<T> {
+    pub fn with_factory(capacity: usize, factory: impl Fn() -> T + Send + Sync + 'static) -> Self {
+        Self {
+            idle: AtomicStack::default(),
+            claimed: AtomicUsize::new(0),
+            popping: AtomicBool::new(false),
+            capacity,
+            factory: Box::new(factory),
+        }
+    }
+    /// Take an idle object, or construct a new one if there is none.
+    ///
+    /// Constructs as well when another thread is taking an idle object at
+    /// the same time, rather than waiting for it.
+    pub fn acquire(&self) -> PoolGuard<'_, T> {
+        let value = self.take().unwrap_or_else(|| (self.factory)());
+        PoolGuard {
+            value: ManuallyDrop::new(value),
+            pool: self,
+        }
+    }
+    /// Number of idle objects.
+    pub fn len(&self) -> usize {
+        self.idle.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    fn take(&self) -> Option<T> {
+        self.popping
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        // `popping` makes this the only popper
+        let value = unsafe { self.idle.boxed_pop() }.map(|value| *value);
+        self.popping.store(false, Ordering::Release);
+        if value.is_some() {
+            self.claimed.fetch_sub(1, Ordering::Relaxed);
+        }
+        value
+    }
+    fn give_back(&self, value: T) {
+        if self.claimed.fetch_add(1, Ordering::Relaxed) >= self.capacity {
+            self.claimed.fetch_sub(1, Ordering::Relaxed);
+            drop(value);
+        } else {
+            self.idle.push(value);
+        }
+    }
+}
+
+/// Object taken from a [`Pool`], returned to it on drop.
+pub struct PoolGuard<'a, T> {
+    value: ManuallyDrop<T>,
+    pool: &'a Pool<T>,
+}
+
+impl<'a, T> PoolGuard<'a, T> {
+    /// Keep the object instead of returning it to the pool.
+    pub fn into_inner(self) -> T {
+        let mut guard = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut guard.value) }
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for PoolGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PoolGuard").field(&*self.value).finish()
+    }
+}
+
+impl<'a, T> Deref for PoolGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'a, T> DerefMut for PoolGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<'a, T> Drop for PoolGuard<'a, T> {
+    fn drop(&mut self) {
+        let value = unsafe { ManuallyDrop::take(&mut self.value) };
+        self.pool.give_back(value);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::Pool;
+
+    fn counting(capacity: usize) -> (Pool<Vec<u8>>, Arc<AtomicUsize>) {
+        let created = Arc::new(AtomicUsize::new(0));
+        let counter = created.clone();
+        let pool = Pool::with_factory(capacity, move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Vec::with_capacity(16)
+        });
+        (pool, created)
+    }
+
+    #[test]
+    fn pool_reuse() {
+        let (pool, created) = counting(2);
+        pool.acquire().push(1);
+        assert_eq!(*pool.acquire(), [1]);
+        assert_eq!(created.load(Ordering::Relaxed), 1);
+
+        let guards: Vec<_> = (0..4).map(|_| pool.acquire()).collect();
+        assert_eq!(created.load(Ordering::Relaxed), 4);
+        drop(guards);
+        assert_eq!(pool.len(), 2);
+
+        let kept = pool.acquire().into_inner();
+        assert_eq!(pool.len(), 1);
+        drop(kept);
+        assert_eq!(pool.len(), 1);
+    }
+    #[test]
+    fn pool_threads() {
+        let (pool, created) = counting(4);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let mut object = pool.acquire();
+                        object.clear();
+                        object.push(0);
+                        assert!(pool.len() <= pool.capacity());
+                    }
+                });
+            }
+        });
+        assert!(pool.len() <= 4);
+        assert!(created.load(Ordering::Relaxed) < 8000);
+    }
+}
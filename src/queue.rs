@@ -0,0 +1,239 @@
+use alloc::boxed::Box;
+use core::{marker::PhantomData, mem::MaybeUninit, ptr};
+
+use crate::{
+    gc::gc::{Global, Local},
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+struct Node<T> {
+    data: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(data: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            data,
+            next: AtomicPtr::default(),
+        }))
+    }
+}
+
+/// Michael-Scott lock-free FIFO queue.
+///
+/// Dequeued nodes are retired through the queue's own [`Global`] instead of
+/// being freed right away, so a concurrent reader never touches freed memory.
+/// Threads operating on the queue repeatedly should keep a [`QueueHandle`],
+/// which registers its [`Local`] once.
+pub struct Queue<T> {
+    /// Sentinel, its `data` is either uninitialized or already moved out.
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    global: Global<Node<T>>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        let sentinel = Node::new(MaybeUninit::uninit());
+        Self {
+            head: AtomicPtr::new(sentinel),
+            tail: AtomicPtr::new(sentinel),
+            global: Default::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        let sentinel = unsafe { Box::from_raw(self.head.load(Ordering::Relaxed)) };
+        let mut next = sentinel.next.load(Ordering::Relaxed);
+        while !next.is_null() {
+            let mut node = unsafe { Box::from_raw(next) };
+            unsafe { node.data.assume_init_drop() };
+            next = node.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    /// Register a participant for the calling thread, see [`QueueHandle`].
+    pub fn handle(&self) -> QueueHandle<'_, T> {
+        QueueHandle {
+            queue: self,
+            local: self.global.register(),
+        }
+    }
+    /// One-off [`QueueHandle::enqueue`], registering a [`Local`] for this
+    /// call alone.
+    pub fn enqueue(&self, value: T) {
+        self.handle().enqueue(value);
+    }
+    /// One-off [`QueueHandle::dequeue`], registering a [`Local`] for this
+    /// call alone.
+    pub fn dequeue(&self) -> Option<T> {
+        self.handle().dequeue()
+    }
+    fn enqueue_with(&self, local: &Local<Node<T>>, value: T) {
+        let _guard = local.pin();
+        let node = Node::new(MaybeUninit::new(value));
+
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            let next = unsafe { &*tail }.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                // help a lagging enqueue move the tail
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if unsafe { &*tail }
+                .next
+                .compare_exchange(next, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+    fn dequeue_with(&self, local: &Local<Node<T>>) -> Option<T> {
+        let guard = local.pin();
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let tail = self.tail.load(Ordering::Acquire);
+            let next = unsafe { &*head }.next.load(Ordering::Acquire);
+            if next.is_null() {
+                return None;
+            }
+            if head == tail {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                // `next` becomes the sentinel, its data is moved out exactly once
+                let value = unsafe { ptr::read((*next).data.as_ptr()) };
+                guard.defer(unsafe { Box::from_raw(head) });
+                return Some(value);
+            }
+        }
+    }
+}
+
+/// A thread's registration with a [`Queue`], reused across operations.
+pub struct QueueHandle<'a, T> {
+    queue: &'a Queue<T>,
+    local: Local<'a, Node<T>>,
+}
+
+impl<'a, T> QueueHandle<'a, T> {
+    pub fn enqueue(&self, value: T) {
+        self.queue.enqueue_with(&self.local, value);
+    }
+    pub fn dequeue(&self) -> Option<T> {
+        self.queue.dequeue_with(&self.local)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+    };
+
+    use super::Queue;
+
+    #[test]
+    fn queue_one() {
+        let queue = Queue::default();
+        assert_eq!(queue.dequeue(), None);
+        for i in 0_usize..10 {
+            queue.enqueue(i);
+        }
+        for i in 0_usize..5 {
+            assert_eq!(queue.dequeue(), Some(i));
+        }
+        queue.enqueue(10);
+        for i in 5_usize..11 {
+            assert_eq!(queue.dequeue(), Some(i));
+        }
+        assert_eq!(queue.dequeue(), None);
+    }
+    #[test]
+    fn queue_handle() {
+        let queue = Queue::default();
+        let handle = queue.handle();
+        for i in 0_usize..10 {
+            handle.enqueue(i);
+        }
+        assert_eq!(queue.dequeue(), Some(0));
+        for i in 1_usize..10 {
+            assert_eq!(handle.dequeue(), Some(i));
+        }
+        assert_eq!(handle.dequeue(), None);
+        // the one-off local is gone, only the handle stays registered
+        assert_eq!(queue.global.stats().registered, 1);
+    }
+    #[test]
+    fn queue_drop() {
+        let queue = Queue::default();
+        for i in 0..10 {
+            queue.enqueue(i.to_string());
+        }
+        assert_eq!(queue.dequeue().as_deref(), Some("0"));
+    }
+    #[test]
+    fn queue_multiple() {
+        let queue = Queue::default();
+        let received = Mutex::new(Vec::new());
+        let count = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for p in 0_usize..4 {
+                let queue = &queue;
+                s.spawn(move || {
+                    let handle = queue.handle();
+                    for i in 0..1000 {
+                        handle.enqueue(p * 1000 + i);
+                    }
+                });
+            }
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let handle = queue.handle();
+                    let mut local = Vec::new();
+                    while count.load(Ordering::Relaxed) < 4000 {
+                        if let Some(x) = handle.dequeue() {
+                            local.push(x);
+                            count.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    received.lock().unwrap().extend(local);
+                });
+            }
+        });
+
+        let mut received = received.into_inner().unwrap();
+        received.sort();
+        assert_eq!(received, (0_usize..4000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,48 @@
+//! Atomics used by the crate, swapped for [loom]'s under `cfg(loom)` so the
+//! lock-free paths can be model checked:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test --release loom
+//! ```
+//!
+//! [loom]: https://docs.rs/loom
+
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic;
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic;
+
+/// Hint for busy waiting, loom needs to yield to make progress.
+#[inline]
+pub(crate) fn spin_loop() {
+    #[cfg(not(loom))]
+    core::hint::spin_loop();
+    #[cfg(loom)]
+    loom::thread::yield_now();
+}
+
+/// Exponential backoff for waiting on another thread: spins for a while, then
+/// yields the thread where `std` is available.
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    /// Spins up to `2^SPIN_LIMIT` times before yielding.
+    const SPIN_LIMIT: u32 = 6;
+
+    pub(crate) fn snooze(&mut self) {
+        if self.step <= Self::SPIN_LIMIT {
+            for _ in 0..1 << self.step {
+                spin_loop();
+            }
+            self.step += 1;
+            return;
+        }
+        #[cfg(all(feature = "std", not(loom)))]
+        std::thread::yield_now();
+        #[cfg(not(all(feature = "std", not(loom))))]
+        spin_loop();
+    }
+}
@@ -0,0 +1,71 @@
+//! Global allocator tracking the allocations of each thread, so tests can
+//! assert that a path doesn't allocate, or simulate allocation failure.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    ptr,
+};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+    /// Allocations left before failing, `None` never fails.
+    static FAIL_AFTER: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // fails during thread teardown, those allocations aren't tracked
+        let fail = FAIL_AFTER
+            .try_with(|left| match left.get() {
+                Some(0) => true,
+                Some(n) => {
+                    left.set(Some(n - 1));
+                    false
+                }
+                None => false,
+            })
+            .unwrap_or(false);
+        if fail {
+            return ptr::null_mut();
+        }
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        let _ = LIVE.try_with(|live| live.set(live.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = LIVE.try_with(|live| live.set(live.get() - 1));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Run `f`, returning its result and how many allocations it made on the
+/// current thread.
+pub fn count<U>(f: impl FnOnce() -> U) -> (U, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+/// Run `f`, returning its result and how many of the allocations it made on
+/// the current thread are still alive.
+pub fn live<U>(f: impl FnOnce() -> U) -> (U, isize) {
+    let before = LIVE.with(Cell::get);
+    let result = f();
+    (result, LIVE.with(Cell::get) - before)
+}
+
+/// Run `f` with every allocation of the current thread failing after the
+/// first `allowed` ones.
+pub fn fail_after<U>(allowed: usize, f: impl FnOnce() -> U) -> U {
+    let old = FAIL_AFTER.with(|left| left.replace(Some(allowed)));
+    let result = f();
+    FAIL_AFTER.with(|left| left.set(old));
+    result
+}